@@ -1,22 +1,173 @@
+# Whether the word being completed is the command sudo runs, skipping
+# sudo's own options and their values
+_complesh_sudo_command() {
+    local i
+    for (( i = 1; i < COMP_CWORD; i++ )); do
+        case "${COMP_WORDS[i]}" in
+            -u|-g|-C|-D|-h|-p|-R|-r|-T|-t|-U) (( ++i < COMP_CWORD )) || return 1 ;;
+            -*) ;;
+            *) return 1 ;;
+        esac
+    done
+    [[ "${COMP_WORDS[COMP_CWORD]}" != -* ]]
+}
+
+# complesh draws on /dev/tty, so its result can be captured from stdout
 _complesh() {
-    path=$(mktemp)
-    complesh -o "${path}" -i "${COMP_WORDS[COMP_CWORD]}"
-    COMPREPLY[0]=$(cat "${path}")
-    rm "${path}"
+    if [ "${COMP_WORDS[0]##*/}" = sudo ] && _complesh_sudo_command; then
+        _complesh_command
+        return
+    fi
+    COMPREPLY[0]=$(complesh -i "${COMP_WORDS[COMP_CWORD]}" --variables "$(compgen -v)" \
+                            --command-name "${COMP_WORDS[0]}" \
+                            --previous-word "${COMP_WORDS[COMP_CWORD-1]}")
 }
 
 _complesh_choices() {
-    path=$(mktemp)
-    complesh -o "${path}" -i "${COMP_WORDS[COMP_CWORD]}" -c "$@"
-    COMPREPLY[0]=$(cat "${path}")
-    rm "${path}"
+    COMPREPLY[0]=$(complesh -i "${COMP_WORDS[COMP_CWORD]}" -c "$@")
 }
 
 if [ -z "$COMPLESH_COMMANDS" ]; then
-    COMPLESH_COMMANDS="ls cd cat wc touch cp mv rm open"
+    COMPLESH_COMMANDS="ls cat wc touch cp mv rm open ssh scp sftp rsync kill strace gdb chown chgrp su sudo make just"
 fi
 
 for command in $COMPLESH_COMMANDS
 do
     complete -F _complesh -o nospace $command
 done
+
+_complesh_jump() {
+    COMPREPLY[0]=$(complesh -i "${COMP_WORDS[COMP_CWORD]}" --jump)
+}
+
+complete -F _complesh_jump -o nospace cd
+
+# Record every directory cd'd into, not just the ones completed
+_complesh_record_cd() {
+    if [ "$PWD" != "$_COMPLESH_LAST_PWD" ]; then
+        _COMPLESH_LAST_PWD="$PWD"
+        complesh --record-dir "$PWD"
+    fi
+}
+
+if [ -n "$COMPLESH_RECORD_CD" ]; then
+    PROMPT_COMMAND="_complesh_record_cd${PROMPT_COMMAND:+; $PROMPT_COMMAND}"
+fi
+
+_complesh_command() {
+    COMPREPLY[0]=$(complesh -i "${COMP_WORDS[COMP_CWORD]}" --command \
+                            --builtins "$(compgen -b)" \
+                            --aliases "$(compgen -a)" \
+                            --functions "$(compgen -A function)")
+}
+
+# Completing the command position needs `complete -I`, added in bash 5
+if (( BASH_VERSINFO[0] >= 5 )); then
+    complete -F _complesh_command -o nospace -I
+fi
+
+_complesh_history() {
+    local line
+    line=$(HISTFILE="${HISTFILE}" complesh -i "${READLINE_LINE}" --history)
+    if [ -n "${line}" ]; then
+        READLINE_LINE="${line% }"
+        READLINE_POINT=${#READLINE_LINE}
+    fi
+}
+
+# Flush this session's commands so they can be searched immediately
+if [ -z "$COMPLESH_NO_HISTORY" ]; then
+    bind -x '"\C-r": history -a; _complesh_history'
+fi
+
+# Original compspecs of the commands handed to complesh_bridge
+declare -A _COMPLESH_SPECS
+
+# Runs a command's original compspec, or completes file names if it had
+# none, leaving the candidates in COMPREPLY
+_complesh_original() {
+    local cmd="${COMP_WORDS[0]##*/}" cur="${COMP_WORDS[COMP_CWORD]}"
+    local prev="${COMP_WORDS[COMP_CWORD-1]}" spec options
+    spec="${_COMPLESH_SPECS[$cmd]}"
+    options="${spec#complete }"
+    options="${options% *}"
+
+    COMPREPLY=()
+    if [[ " $options " =~ \ -F\ ([^ ]+)\  ]]; then
+        "${BASH_REMATCH[1]}" "$cmd" "$cur" "$prev"
+    else
+        local IFS=$'\n'
+        COMPREPLY=( $(eval "compgen ${options:--f} -- \"\$cur\"") )
+    fi
+}
+
+# Runs a command's original compspec and shows the candidates it produces
+# in the dropdown
+_complesh_bridge() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    _complesh_original
+
+    if (( ${#COMPREPLY[@]} > 1 )); then
+        COMPREPLY=( "$(printf '%s\0' "${COMPREPLY[@]}" | complesh -i "${cur}" -0 --file -)" )
+    fi
+}
+
+# The compspec bash has for a command, loading it first if bash-completion
+# registers completions on demand
+_complesh_spec() {
+    local spec
+    spec=$(complete -p "$1" 2>/dev/null)
+    if [ -z "$spec" ] && declare -F _completion_loader >/dev/null; then
+        _completion_loader "$1"
+        spec=$(complete -p "$1" 2>/dev/null)
+    fi
+    printf '%s' "$spec"
+}
+
+# The `-o` options of a compspec, such as `-o filenames -o default`, so
+# candidates are still quoted and spaced the way the command expects
+_complesh_spec_options() {
+    local word previous words
+    read -ra words <<< "$1"
+    for word in "${words[@]}"; do
+        [ "$previous" = -o ] && printf -- '-o %s ' "$word"
+        previous="$word"
+    done
+}
+
+# Routes the existing bash completion of each command through complesh
+complesh_bridge() {
+    local cmd spec
+    for cmd in "$@"; do
+        spec=$(_complesh_spec "$cmd")
+        if [ -n "$spec" ]; then
+            _COMPLESH_SPECS[$cmd]="$spec"
+            complete -F _complesh_bridge $(_complesh_spec_options "$spec") "$cmd"
+        fi
+    done
+}
+
+if [ -n "$COMPLESH_BRIDGE_COMMANDS" ]; then
+    complesh_bridge $COMPLESH_BRIDGE_COMMANDS
+fi
+
+# Completes script and target names after `run`, `--bin`, `--example` and
+# `--test`, leaving every other argument to the command's own completion
+_complesh_targets() {
+    case "${COMP_WORDS[0]##*/} ${COMP_WORDS[COMP_CWORD-1]}" in
+        "npm run"|"yarn run"|"pnpm run"|"cargo --bin"|"cargo --example"|"cargo --test")
+            _complesh ;;
+        *)
+            _complesh_original ;;
+    esac
+}
+
+if [ -z "$COMPLESH_TARGET_COMMANDS" ]; then
+    COMPLESH_TARGET_COMMANDS="npm yarn pnpm cargo"
+fi
+
+for command in $COMPLESH_TARGET_COMMANDS
+do
+    _COMPLESH_SPECS[$command]=$(_complesh_spec "$command")
+    complete -F _complesh_targets $(_complesh_spec_options "${_COMPLESH_SPECS[$command]}") $command
+done
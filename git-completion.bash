@@ -3071,14 +3071,6 @@ __git_func_wrap ()
 	$1
 }
 
-_complesh_function() {
-    path=$(mktemp)
-    CDIR=/Users/jmiller/jsm/complesh
-    "${CDIR}/target/release/complesh" -o "${path}" -i "${COMP_WORDS[COMP_CWORD]}"
-    COMPREPLY[0]=$(cat "${path}")
-    rm "${path}"
-}
-
 # Setup completion for certain functions defined above by setting common
 # variables and workarounds.
 # This is NOT a public function; use at your own risk.
@@ -3088,8 +3080,6 @@ __git_complete ()
 	eval "$wrapper () { __git_func_wrap $2 ; }"
 	complete -o bashdefault -o default -o nospace -F $wrapper $1 2>/dev/null \
 		|| complete -o default -o nospace -F $wrapper $1
-	# complete -o bashdefault -o default -o nospace -F _complesh_function $1 2>/dev/null \
-	# 	|| complete -o default -o nospace -F _complesh_function $1
 }
 
 # wrapper for backwards compatibility
@@ -0,0 +1,101 @@
+use ::util::read_file;
+use std::collections::HashMap;
+
+lazy_static! {
+    static ref USERS: Vec<User> = parse_passwd(&read_file("/etc/passwd"));
+    static ref GROUPS: Vec<Group> = parse_group(&read_file("/etc/group"));
+}
+
+/// A line of `/etc/passwd`
+#[derive(Clone, Debug, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub uid: u32,
+    pub gecos: String,
+    pub home: String,
+}
+
+/// A line of `/etc/group`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub name: String,
+    pub gid: u32,
+    pub members: Vec<String>,
+}
+
+pub fn parse_passwd(contents: &str) -> Vec<User> {
+    contents.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split(':').collect();
+            if fields.len() < 7 {
+                return None
+            }
+            Some(User {
+                name: fields[0].to_string(),
+                uid: fields[2].parse().ok()?,
+                gecos: fields[4].to_string(),
+                home: fields[5].to_string(),
+            })
+        })
+        .collect()
+}
+
+pub fn parse_group(contents: &str) -> Vec<Group> {
+    contents.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split(':').collect();
+            if fields.len() < 4 {
+                return None
+            }
+            Some(Group {
+                name: fields[0].to_string(),
+                gid: fields[2].parse().ok()?,
+                members: fields[3].split(',').filter(|m| !m.is_empty()).map(str::to_string).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Local accounts, read once per process
+pub fn users() -> &'static [User] {
+    &USERS
+}
+
+pub fn groups() -> &'static [Group] {
+    &GROUPS
+}
+
+pub fn user(name: &str) -> Option<&'static User> {
+    users().iter().find(|user| user.name == name)
+}
+
+/// Maps uids to user names
+pub fn user_names() -> HashMap<u32, String> {
+    users().iter().map(|user| (user.uid, user.name.clone())).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_passwd, parse_group, Group, User};
+
+    #[test]
+    fn test_parse_passwd() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\n# comment\nbroken:x\n\
+                      alice:x:1000:1000:Alice,,,:/home/alice:/bin/zsh\n";
+        assert_eq!(parse_passwd(passwd), vec![
+            User { name: "root".to_string(), uid: 0, gecos: "root".to_string(), home: "/root".to_string() },
+            User { name: "alice".to_string(), uid: 1000, gecos: "Alice,,,".to_string(), home: "/home/alice".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_group() {
+        let group = "wheel:x:10:alice,bob\nusers:x:100:\n";
+        assert_eq!(parse_group(group), vec![
+            Group { name: "wheel".to_string(), gid: 10, members: vec!["alice".to_string(), "bob".to_string()] },
+            Group { name: "users".to_string(), gid: 100, members: vec![] },
+        ]);
+    }
+}
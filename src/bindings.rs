@@ -0,0 +1,318 @@
+use ::config::Config;
+use ::errors::Result;
+use ::readkeys::parse_key;
+use std::collections::HashMap;
+use termion::event::Key;
+
+/// What a key can be bound to, named in the config as in `ACTIONS`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Accept,
+    AcceptRaw,
+    Cancel,
+    Complete,
+    Next,
+    Prev,
+    ToggleMode,
+    ToggleMark,
+    BackwardChar,
+    ForwardChar,
+    BackwardWord,
+    ForwardWord,
+    BeginningOfLine,
+    EndOfLine,
+    BackwardDeleteChar,
+    DeleteChar,
+    DeleteCharOrCancel,
+    BackwardKillWord,
+    KillWord,
+    BackwardKillLine,
+    KillLine,
+    Yank,
+    YankPop,
+    Undo,
+    ViNormal,
+    ViInsert,
+    ViAppend,
+    ViInsertAtStart,
+    ViAppendAtEnd,
+    ViChangeToEnd,
+    ViChangeLine,
+    /// Unbinds a key
+    Ignore,
+}
+
+const ACTIONS: &[(&str, Action)] = &[
+    ("accept", Action::Accept),
+    ("accept-raw", Action::AcceptRaw),
+    ("cancel", Action::Cancel),
+    ("complete", Action::Complete),
+    ("next", Action::Next),
+    ("prev", Action::Prev),
+    ("toggle-mode", Action::ToggleMode),
+    ("toggle-mark", Action::ToggleMark),
+    ("backward-char", Action::BackwardChar),
+    ("forward-char", Action::ForwardChar),
+    ("backward-word", Action::BackwardWord),
+    ("forward-word", Action::ForwardWord),
+    ("beginning-of-line", Action::BeginningOfLine),
+    ("end-of-line", Action::EndOfLine),
+    ("backward-delete-char", Action::BackwardDeleteChar),
+    ("delete-char", Action::DeleteChar),
+    ("delete-char-or-cancel", Action::DeleteCharOrCancel),
+    ("backward-kill-word", Action::BackwardKillWord),
+    ("kill-word", Action::KillWord),
+    ("backward-kill-line", Action::BackwardKillLine),
+    ("kill-line", Action::KillLine),
+    ("yank", Action::Yank),
+    ("yank-pop", Action::YankPop),
+    ("undo", Action::Undo),
+    ("vi-normal", Action::ViNormal),
+    ("vi-insert", Action::ViInsert),
+    ("vi-append", Action::ViAppend),
+    ("vi-insert-at-start", Action::ViInsertAtStart),
+    ("vi-append-at-end", Action::ViAppendAtEnd),
+    ("vi-change-to-end", Action::ViChangeToEnd),
+    ("vi-change-line", Action::ViChangeLine),
+    ("ignore", Action::Ignore),
+];
+
+/// Keys for typing a query, in emacs mode and vi's insert state
+const INSERT: &[(Key, Action)] = &[
+    (Key::Char('\n'), Action::Accept),
+    (Key::Alt('\r'), Action::AcceptRaw),
+    (Key::Esc, Action::Cancel),
+    (Key::Ctrl('c'), Action::Cancel),
+    (Key::Ctrl('g'), Action::Cancel),
+    (Key::Ctrl('d'), Action::DeleteCharOrCancel),
+    (Key::Char('\t'), Action::Complete),
+    (Key::Ctrl('n'), Action::Next),
+    (Key::Alt('n'), Action::Next),
+    (Key::Down, Action::Next),
+    (Key::Ctrl('p'), Action::Prev),
+    (Key::Alt('p'), Action::Prev),
+    (Key::Up, Action::Prev),
+    (Key::Null, Action::ToggleMode),
+    (Key::Backspace, Action::BackwardDeleteChar),
+    (Key::Ctrl('h'), Action::BackwardDeleteChar),
+    (Key::Delete, Action::DeleteChar),
+    (Key::Ctrl('a'), Action::BeginningOfLine),
+    (Key::Home, Action::BeginningOfLine),
+    (Key::Ctrl('e'), Action::EndOfLine),
+    (Key::End, Action::EndOfLine),
+    (Key::Ctrl('b'), Action::BackwardChar),
+    (Key::Left, Action::BackwardChar),
+    (Key::Ctrl('f'), Action::ForwardChar),
+    (Key::Right, Action::ForwardChar),
+    (Key::Alt('b'), Action::BackwardWord),
+    (Key::CtrlLeft, Action::BackwardWord),
+    (Key::Alt('f'), Action::ForwardWord),
+    (Key::CtrlRight, Action::ForwardWord),
+    (Key::Alt('\u{7f}'), Action::BackwardKillWord),
+    (Key::Alt('d'), Action::KillWord),
+    (Key::Ctrl('u'), Action::BackwardKillLine),
+    (Key::Ctrl('k'), Action::KillLine),
+    (Key::Ctrl('y'), Action::Yank),
+    (Key::Alt('y'), Action::YankPop),
+    (Key::Ctrl('7'), Action::Undo),
+];
+
+/// Changes to `INSERT` in vi mode
+const VI_INSERT: &[(Key, Action)] = &[
+    (Key::Esc, Action::ViNormal),
+];
+
+/// Keys in vi's normal state, where typing doesn't insert
+const NORMAL: &[(Key, Action)] = &[
+    (Key::Char('\n'), Action::Accept),
+    (Key::Alt('\r'), Action::AcceptRaw),
+    (Key::Esc, Action::Cancel),
+    (Key::Ctrl('c'), Action::Cancel),
+    (Key::Char('\t'), Action::Complete),
+    (Key::Char('j'), Action::Next),
+    (Key::Ctrl('n'), Action::Next),
+    (Key::Down, Action::Next),
+    (Key::Char('k'), Action::Prev),
+    (Key::Ctrl('p'), Action::Prev),
+    (Key::Up, Action::Prev),
+    (Key::Null, Action::ToggleMode),
+    (Key::Char('h'), Action::BackwardChar),
+    (Key::Left, Action::BackwardChar),
+    (Key::Backspace, Action::BackwardChar),
+    (Key::Char('l'), Action::ForwardChar),
+    (Key::Right, Action::ForwardChar),
+    (Key::Char('b'), Action::BackwardWord),
+    (Key::CtrlLeft, Action::BackwardWord),
+    (Key::Char('w'), Action::ForwardWord),
+    (Key::Char('e'), Action::ForwardWord),
+    (Key::CtrlRight, Action::ForwardWord),
+    (Key::Char('0'), Action::BeginningOfLine),
+    (Key::Char('^'), Action::BeginningOfLine),
+    (Key::Home, Action::BeginningOfLine),
+    (Key::Char('$'), Action::EndOfLine),
+    (Key::End, Action::EndOfLine),
+    (Key::Char('x'), Action::DeleteChar),
+    (Key::Delete, Action::DeleteChar),
+    (Key::Char('X'), Action::BackwardDeleteChar),
+    (Key::Char('D'), Action::KillLine),
+    (Key::Char('p'), Action::Yank),
+    (Key::Char('u'), Action::Undo),
+    (Key::Char('i'), Action::ViInsert),
+    (Key::Char('a'), Action::ViAppend),
+    (Key::Char('I'), Action::ViInsertAtStart),
+    (Key::Char('A'), Action::ViAppendAtEnd),
+    (Key::Char('C'), Action::ViChangeToEnd),
+    (Key::Char('S'), Action::ViChangeLine),
+];
+
+/// Whether the terminal can send `key` as itself. Ctrl-j and Ctrl-m
+/// arrive as enter, Ctrl-i as tab and Ctrl-[ as escape.
+fn deliverable(key: Key) -> bool {
+    !matches!(key, Key::Ctrl('j') | Key::Ctrl('m') | Key::Ctrl('i') | Key::Ctrl('['))
+}
+
+impl Action {
+    pub fn parse(name: &str) -> Option<Action> {
+        ACTIONS.iter().find(|&&(action_name, _)| action_name == name).map(|&(_, action)| action)
+    }
+
+    pub fn name(self) -> &'static str {
+        ACTIONS.iter().find(|&&(_, action)| action == self).map(|&(name, _)| name).unwrap_or("")
+    }
+}
+
+/// The set of bindings keys are looked up in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Keymap {
+    /// Typing inserts, as in emacs mode or vi's insert state
+    Insert,
+    /// vi's normal state
+    Normal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EditingMode {
+    Emacs,
+    Vi,
+}
+
+/// Which action each key performs. Printable keys without a binding are
+/// typed into the query in the insert keymap.
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    pub mode: EditingMode,
+    insert: HashMap<Key, Action>,
+    normal: HashMap<Key, Action>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            mode: EditingMode::Emacs,
+            insert: INSERT.iter().cloned().collect(),
+            normal: HashMap::new(),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn vi() -> Self {
+        let mut bindings = Bindings {
+            mode: EditingMode::Vi,
+            normal: NORMAL.iter().cloned().collect(),
+            ..Bindings::default()
+        };
+        bindings.insert.extend(VI_INSERT.iter().cloned());
+        bindings
+    }
+
+    /// Reads `editing-mode = vi` and `<key> = <action>` lines under
+    /// `[bindings]`, and vi's normal state bindings under
+    /// `[vi-normal-bindings]`:
+    ///
+    /// ```text
+    /// [bindings]
+    /// editing-mode = vi
+    /// ctrl-o = accept-raw
+    /// alt-j = next
+    /// ```
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut bindings = match config.get("bindings", "editing-mode") {
+            Some("vi") => Bindings::vi(),
+            Some("emacs") | None => Bindings::default(),
+            Some(mode) => return Err(format!("unknown editing mode: {}", mode).into()),
+        };
+        for (key, action) in config.section("bindings").filter(|&(key, _)| key != "editing-mode") {
+            bindings.bind(Keymap::Insert, key, action)?;
+        }
+        for (key, action) in config.section("vi-normal-bindings") {
+            bindings.bind(Keymap::Normal, key, action)?;
+        }
+        Ok(bindings)
+    }
+
+    pub fn bind(&mut self, keymap: Keymap, key: &str, action: &str) -> Result<&mut Self> {
+        let name = key;
+        let key = parse_key(name).ok_or_else(|| format!("unknown key: {}", name))?;
+        if !deliverable(key) {
+            return Err(format!("{} can't be bound, the terminal sends it as another key", name).into())
+        }
+        let action = Action::parse(action).ok_or_else(|| format!("unknown action: {}", action))?;
+        match keymap {
+            Keymap::Insert => self.insert.insert(key, action),
+            Keymap::Normal => self.normal.insert(key, action),
+        };
+        Ok(self)
+    }
+
+    pub fn action(&self, keymap: Keymap, key: Key) -> Option<Action> {
+        match keymap {
+            Keymap::Insert => self.insert.get(&key).cloned(),
+            Keymap::Normal => self.normal.get(&key).cloned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Action, Bindings, EditingMode, Keymap, INSERT, NORMAL, VI_INSERT, deliverable};
+    use ::config::Config;
+    use termion::event::Key;
+
+    #[test]
+    fn test_config_overrides_defaults() {
+        let config = Config::parse("[bindings]\nctrl-o = accept-raw\nctrl-n = ignore\n[vi-normal-bindings]\nq = cancel\n");
+        let bindings = Bindings::from_config(&config).unwrap();
+        assert_eq!(bindings.mode, EditingMode::Emacs);
+        assert_eq!(bindings.action(Keymap::Insert, Key::Ctrl('o')), Some(Action::AcceptRaw));
+        assert_eq!(bindings.action(Keymap::Insert, Key::Ctrl('n')), Some(Action::Ignore));
+        assert_eq!(bindings.action(Keymap::Insert, Key::Esc), Some(Action::Cancel));
+        assert_eq!(bindings.action(Keymap::Normal, Key::Char('q')), Some(Action::Cancel));
+    }
+
+    #[test]
+    fn test_vi_mode() {
+        let bindings = Bindings::from_config(&Config::parse("[bindings]\nediting-mode = vi\n")).unwrap();
+        assert_eq!(bindings.action(Keymap::Insert, Key::Esc), Some(Action::ViNormal));
+        assert_eq!(bindings.action(Keymap::Normal, Key::Char('x')), Some(Action::DeleteChar));
+        assert_eq!(bindings.action(Keymap::Insert, Key::Char('x')), None);
+    }
+
+    #[test]
+    fn test_config_errors() {
+        assert!(Bindings::from_config(&Config::parse("[bindings]\nctrl-o = fly\n")).is_err());
+        assert!(Bindings::from_config(&Config::parse("[bindings]\nhyper-o = accept\n")).is_err());
+        assert!(Bindings::from_config(&Config::parse("[bindings]\nediting-mode = ed\n")).is_err());
+        assert_eq!(Action::parse(Action::KillWord.name()), Some(Action::KillWord));
+    }
+
+    #[test]
+    fn test_defaults_use_deliverable_keys() {
+        for bindings in &[Bindings::default(), Bindings::vi()] {
+            let keymap = if bindings.mode == EditingMode::Vi { Keymap::Normal } else { Keymap::Insert };
+            assert_eq!(bindings.action(keymap, Key::Alt('\r')), Some(Action::AcceptRaw));
+        }
+        assert!(INSERT.iter().chain(VI_INSERT).chain(NORMAL).all(|&(key, _)| deliverable(key)));
+        assert!(Bindings::from_config(&Config::parse("[bindings]\nctrl-j = accept-raw\n")).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use ::completer::{Completer, CompleterBase};
+use ::filter::{Filter, WeightedMatch};
+use ::repository::{Repository, git_output};
+use ::util::{read_file, search_root, path_string};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// Completes files changed on the current branch since it diverged from a
+/// base branch, along with uncommitted and untracked changes
+pub struct ChangedCompleter {
+    base: CompleterBase,
+    pub base_branch: Option<String>,
+    /// How long a listing is reused while nothing is staged or committed,
+    /// which is how long an edit or a new file can go unnoticed
+    pub refresh: Duration,
+    /// The repository state the merge base was found for, and the merge base
+    merge_base: Option<(String, Option<String>)>,
+    /// The key the changed files are cached under and when they were listed
+    listed: Option<(String, Instant)>,
+}
+
+impl Default for ChangedCompleter {
+    fn default() -> ChangedCompleter {
+        ChangedCompleter {
+            base: CompleterBase::new(),
+            base_branch: None,
+            refresh: Duration::from_secs(2),
+            merge_base: None,
+            listed: None,
+        }
+    }
+}
+
+fn modified<P: AsRef<Path>>(path: P) -> u64 {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_secs() * 1_000_000_000 + u64::from(since.subsec_nanos()))
+        .unwrap_or(0)
+}
+
+/// Where HEAD points and when that last moved, read without running git
+fn head_state(repository: &Repository) -> String {
+    let head = read_file(repository.git_dir.join("HEAD"));
+    let head = head.trim();
+    let moved = match head.strip_prefix("ref:") {
+        Some(name) => modified(repository.common_dir.join(name.trim())) + modified(repository.common_dir.join("packed-refs")),
+        None => 0,
+    };
+    format!("{}#{}", head, moved)
+}
+
+fn first_line(output: Option<String>) -> Option<String> {
+    output
+        .and_then(|output| output.lines().next().map(str::to_string))
+        .and_then(|line| if line.is_empty() { None } else { Some(line) })
+}
+
+/// The branch `origin/HEAD` points at, e.g. `origin/master`
+fn default_branch<P: AsRef<Path>>(root: P) -> String {
+    let symbolic = git_output(root, &["symbolic-ref", "--quiet", "--short", "refs/remotes/origin/HEAD"]);
+    first_line(symbolic).unwrap_or(String::from("master"))
+}
+
+fn changed_files<P: AsRef<Path>>(root: P, merge_base: &str) -> Vec<String> {
+    let root = root.as_ref();
+    let diff = git_output(root, &["diff", "--name-only", merge_base]);
+    let untracked = git_output(root, &["ls-files", "--others", "--exclude-standard"]);
+
+    let mut paths: Vec<_> = diff.unwrap_or_default().lines()
+        .chain(untracked.unwrap_or_default().lines())
+        .map(|path| root.join(path))
+        .filter(|path| path.exists())
+        .map(path_string)
+        .collect();
+
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+impl ChangedCompleter {
+    pub fn base_branch<S: Into<String>>(&mut self, base_branch: S) -> &mut Self {
+        self.base_branch = Some(base_branch.into());
+        self
+    }
+
+    /// Finds the merge base again only once HEAD or the base branch moves
+    fn merge_base(&mut self, root: &Path, state: String) -> Option<String> {
+        if let Some((ref found_for, ref merge_base)) = self.merge_base {
+            if *found_for == state {
+                return merge_base.clone()
+            }
+        }
+        let base_branch = self.base_branch.clone().unwrap_or_else(|| default_branch(root));
+        let merge_base = first_line(git_output(root, &["merge-base", "HEAD", &*base_branch]));
+        self.merge_base = Some((state, merge_base.clone()));
+        merge_base
+    }
+
+    /// Lists again when the index changes or the listing is older than
+    /// `refresh`, since edits and new files don't touch the index
+    fn listing_key(&mut self, key: String) -> String {
+        let stale = match self.listed {
+            Some((ref listed, at)) => *listed != key || at.elapsed() >= self.refresh,
+            None => true,
+        };
+        if stale {
+            if let Some((listed, _)) = self.listed.take() {
+                self.base.forget(&listed);
+            }
+            self.listed = Some((key.clone(), Instant::now()));
+        }
+        key
+    }
+}
+
+impl Completer for ChangedCompleter {
+    fn label(&self) -> String {
+        "changed".to_string()
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        let repository = match Repository::discover(search_root(query)) {
+            Some(repository) => repository,
+            None => return vec![],
+        };
+        let root = repository.work_tree.clone();
+
+        let state = format!("{}@{}@{}", path_string(&root), head_state(&repository),
+                            self.base_branch.clone().unwrap_or_default());
+        let merge_base = match self.merge_base(&root, state.clone()) {
+            Some(merge_base) => merge_base,
+            None => return vec![],
+        };
+
+        let index = modified(repository.git_dir.join("index"));
+        let key = self.listing_key(format!("{}@{}#{}", state, merge_base, index));
+        self.base.matches::<F, _>(query, &key, || changed_files(&root, &merge_base))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChangedCompleter;
+    use ::completer::Completer;
+    use ::filter::SpacedFilter;
+    use ::readkeys::Printable;
+    use ::util::{fixture, path_string};
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+    use std::time::Duration;
+
+    fn git(root: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=complesh", "-c", "user.email=complesh@localhost"])
+            .args(args)
+            .current_dir(root)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    fn changed(completer: &mut ChangedCompleter, root: &Path) -> Vec<String> {
+        let query = format!("{}/", path_string(root));
+        let mut paths: Vec<_> = completer.complete::<SpacedFilter>(&query).iter()
+            .map(|path| path.without_escape_codes())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_changed_files() {
+        let root = fixture("changed");
+        git(&root, &["init", "-q"]);
+        fs::write(root.join("committed.txt"), "one").unwrap();
+        git(&root, &["add", "."]);
+        git(&root, &["commit", "-qm", "Start"]);
+
+        let mut completer = ChangedCompleter::default();
+        completer.base_branch("HEAD");
+        assert!(changed(&mut completer, &root).is_empty());
+
+        fs::write(root.join("staged.txt"), "two").unwrap();
+        git(&root, &["add", "staged.txt"]);
+        assert_eq!(changed(&mut completer, &root), vec![path_string(root.join("staged.txt"))]);
+
+        completer.refresh = Duration::from_secs(0);
+        fs::write(root.join("committed.txt"), "three").unwrap();
+        fs::write(root.join("untracked.txt"), "four").unwrap();
+        assert_eq!(changed(&mut completer, &root), vec![
+            path_string(root.join("committed.txt")),
+            path_string(root.join("staged.txt")),
+            path_string(root.join("untracked.txt")),
+        ]);
+
+        git(&root, &["add", "."]);
+        git(&root, &["commit", "-qm", "Change"]);
+        assert!(changed(&mut completer, &root).is_empty());
+    }
+}
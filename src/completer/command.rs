@@ -0,0 +1,215 @@
+use ::completer::{Completer, CompleterBase};
+use ::filter::{Filter, WeightedMatch};
+use ::readkeys::Printable;
+use ::util::{cache_dir, describe, path_string, read_file};
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Completes command names for the first word of a command line: every
+/// executable on `$PATH` plus the shell's builtins, aliases and functions
+pub struct CommandCompleter {
+    base: CompleterBase,
+    shell: Vec<(String, &'static str)>,
+    /// The `$PATH` key the executables are cached under in `base`
+    listed: Option<String>,
+}
+
+impl Default for CommandCompleter {
+    fn default() -> CommandCompleter {
+        CommandCompleter {
+            base: CompleterBase::new(),
+            shell: vec![],
+            listed: None,
+        }
+    }
+}
+
+fn path_dirs() -> Vec<PathBuf> {
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).filter(|dir| dir.is_dir()).collect())
+        .unwrap_or_default()
+}
+
+/// Identifies the current contents of `$PATH`; it changes whenever a
+/// directory is added, removed or has an entry added or removed
+fn path_key(dirs: &[PathBuf]) -> String {
+    dirs.iter()
+        .map(|dir| {
+            let mtime = fs::metadata(dir).and_then(|meta| meta.modified()).ok()
+                .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+                .map(|mtime| mtime.as_secs())
+                .unwrap_or(0);
+            format!("{}@{}", path_string(dir), mtime)
+        })
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn is_executable<P: AsRef<Path>>(path: P) -> bool {
+    fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Lists executables described by their directory, keeping only the first
+/// of each name the way `$PATH` lookup does
+fn scan_executables(dirs: &[PathBuf]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut executables = vec![];
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let mut names: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| is_executable(entry.path()))
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        for name in names {
+            if seen.insert(name.clone()) {
+                executables.push(describe(name, path_string(dir)));
+            }
+        }
+    }
+    executables
+}
+
+fn cache_path() -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join("commands"))
+}
+
+fn read_cache<P: AsRef<Path>>(path: P, key: &str) -> Option<Vec<String>> {
+    let contents = read_file(path);
+    let mut lines = contents.lines();
+    if lines.next() != Some(key) {
+        return None
+    }
+    Some(lines.map(str::to_string).collect())
+}
+
+fn write_cache<P: AsRef<Path>>(path: P, key: &str, executables: &[String]) {
+    let _ = path.as_ref().parent().map(fs::create_dir_all);
+    if let Ok(mut file) = File::create(path) {
+        let _ = writeln!(file, "{}\n{}", key, executables.join("\n"));
+    }
+}
+
+fn executables(dirs: &[PathBuf], key: &str) -> Vec<String> {
+    let cache = cache_path();
+    cache.as_ref().and_then(|cache| read_cache(cache, key)).unwrap_or_else(|| {
+        let executables = scan_executables(dirs);
+        if let Some(cache) = cache {
+            write_cache(cache, key, &executables);
+        }
+        executables
+    })
+}
+
+impl CommandCompleter {
+    fn shell_names(&mut self, names: Vec<String>, kind: &'static str) -> &mut Self {
+        self.shell.extend(names.into_iter().map(|name| (name, kind)));
+        self
+    }
+
+    pub fn builtins(&mut self, names: Vec<String>) -> &mut Self {
+        self.shell_names(names, "builtin")
+    }
+
+    pub fn aliases(&mut self, names: Vec<String>) -> &mut Self {
+        self.shell_names(names, "alias")
+    }
+
+    pub fn functions(&mut self, names: Vec<String>) -> &mut Self {
+        self.shell_names(names, "function")
+    }
+}
+
+impl Completer for CommandCompleter {
+    fn label(&self) -> String {
+        "command".to_string()
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        let shadowed: HashSet<_> = self.shell.iter().map(|(name, _)| name.clone()).collect();
+        let shell: Vec<_> = self.shell.iter().map(|&(ref name, kind)| describe(name, kind)).collect();
+
+        // Keyed like the cache on disk, so a long-lived completer notices
+        // executables being installed or removed
+        let dirs = path_dirs();
+        let key = path_key(&dirs);
+        if let Some(listed) = self.listed.replace(key.clone()) {
+            if listed != key {
+                self.base.forget(&listed);
+            }
+        }
+
+        // Shell names shadow executables of the same name
+        self.base.matches::<F, _>(query, &key, || {
+            let executables = executables(&dirs, &key).into_iter()
+                .filter(|executable| !shadowed.contains(&executable.value()));
+            shell.into_iter().chain(executables).collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CommandCompleter, read_cache, scan_executables, write_cache};
+    use ::completer::Completer;
+    use ::filter::SpacedFilter;
+    use ::readkeys::Printable;
+    use ::util::{describe, fixture, path_string};
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    fn executable<P: AsRef<Path>>(path: P) {
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_scan_executables() {
+        let root = fixture("command-path");
+        let (first, second) = (root.join("first"), root.join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        executable(first.join("tool"));
+        fs::write(first.join("README"), "").unwrap();
+        executable(second.join("tool"));
+        executable(second.join("other"));
+
+        assert_eq!(scan_executables(&[first.clone(), second.clone()]), vec![
+            describe("tool", path_string(&first)),
+            describe("other", path_string(&second)),
+        ]);
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let path = fixture("command-cache").join("commands");
+        let executables = vec![describe("tool", "/usr/bin"), describe("other", "/bin")];
+        write_cache(&path, "/usr/bin@1:/bin@2", &executables);
+
+        assert_eq!(read_cache(&path, "/usr/bin@1:/bin@2"), Some(executables));
+        assert_eq!(read_cache(&path, "/usr/bin@3:/bin@2"), None);
+    }
+
+    #[test]
+    fn test_shell_names_shadow_executables() {
+        let mut completer = CommandCompleter::default();
+        completer.builtins(vec!["sh".to_string()]);
+        let rows: Vec<_> = completer.complete::<SpacedFilter>("sh").iter()
+            .map(|row| row.without_escape_codes())
+            .filter(|row| row.value() == "sh")
+            .collect();
+        assert_eq!(rows, vec![describe("sh", "builtin")]);
+    }
+}
@@ -1,27 +1,51 @@
 use ::completer::{Completer, CompleterBase};
-use ::filter::Filter;
-use ::ring_buffer::RingBuffer;
-use ::util::{git_root, search_root, path_string};
+use ::filter::{Filter, WeightedMatch};
+use ::repository::{DISCOVERY_TTL, Repository, git_output};
+use ::util::{search_root, path_string};
 use crossbeam::sync::MsQueue;
 use ignore::WalkState::Continue;
 use ignore::{WalkBuilder, DirEntry};
 use std::sync::Arc;
 use std::path::Path;
+use std::time::Instant;
+
+/// How paths inside submodules are completed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Submodules {
+    /// Walk into every submodule, applying its own ignore rules
+    Recurse,
+    /// Show submodule roots as directories without their contents
+    RootsOnly,
+    /// Stay inside the submodule containing the query
+    Current,
+}
 
 pub struct GitCompleter {
     base: CompleterBase,
     pub max_depth: usize,
     pub root: String,
+    pub submodules: Submodules,
+    repository: Option<Repository>,
+    /// The last `<root>/<revision>` looked up, when, and the commit it
+    /// resolved to, so typing after the `:` doesn't run git each time.
+    /// It is looked up again after `DISCOVERY_TTL` in case the branch moved.
+    resolved: Option<(String, Instant, Option<String>)>,
 }
 
-fn walk_dir_ignore<P: AsRef<Path>>(root: P, max_depth: usize) -> Vec<String> {
+fn walk_dir_ignore<P: AsRef<Path>>(root: P, common_dir: P, max_depth: usize) -> Vec<String> {
     let queue: Arc<MsQueue<Option<DirEntry>>> = Arc::new(MsQueue::new());
     let stdout_queue = queue.clone();
 
-    let walker = WalkBuilder::new(root.as_ref())
-        .threads(8)
-        .max_depth(Some(max_depth))
-        .build_parallel();
+    let mut builder = WalkBuilder::new(root.as_ref());
+    builder.threads(8).max_depth(Some(max_depth));
+
+    // Linked worktrees and submodules have a `.git` file, so the walker
+    // can't find the shared exclude file on its own
+    if !root.as_ref().join(".git").is_dir() {
+        builder.add_ignore(common_dir.as_ref().join("info").join("exclude"));
+    }
+
+    let walker = builder.build_parallel();
 
     walker.run(|| {
         let queue = queue.clone();
@@ -37,24 +61,114 @@ fn walk_dir_ignore<P: AsRef<Path>>(root: P, max_depth: usize) -> Vec<String> {
     paths
 }
 
+fn walk_repository(repository: &Repository, max_depth: usize, submodules: Submodules) -> Vec<String> {
+    let nested = repository.submodules();
+    let mut paths: Vec<_> = walk_dir_ignore(&repository.work_tree, &repository.common_dir, max_depth)
+        .into_iter()
+        .filter(|path| !nested.iter().any(|submodule| Path::new(path).starts_with(submodule)))
+        .collect();
+
+    for submodule in nested {
+        match (submodules, Repository::discover(&submodule)) {
+            (Submodules::Recurse, Some(ref repository)) if repository.work_tree == submodule => {
+                paths.extend(walk_repository(repository, max_depth, submodules))
+            }
+            _ => paths.push(format!("{}/", path_string(submodule))),
+        }
+    }
+    paths
+}
+
+/// Splits a `<ref>:<partial>` query into the revision and the partial path
+fn split_revision(query: &str) -> Option<(&str, &str)> {
+    let idx = match query.find(':') {
+        Some(idx) => idx,
+        None => return None,
+    };
+    let (revision, partial) = (&query[..idx], &query[idx + 1..]);
+    if revision.is_empty() || revision.starts_with(|c| c == '/' || c == '~' || c == '.') {
+        return None
+    }
+    Some((revision, partial))
+}
+
+/// Resolves a revision to the commit id it currently points at
+fn resolve_revision<P: AsRef<Path>>(root: P, revision: &str) -> Option<String> {
+    let spec = format!("{}^{{commit}}", revision);
+    git_output(root, &["rev-parse", "--verify", "--quiet", &*spec])
+        .map(|commit| commit.trim().to_string())
+        .and_then(|commit| if commit.is_empty() { None } else { Some(commit) })
+}
+
+/// Lists the tree of a commit as `<revision>:<path>` with directories suffixed by `/`
+fn walk_revision<P: AsRef<Path>>(root: P, revision: &str, commit: &str) -> Vec<String> {
+    let listing = git_output(root, &["ls-tree", "-r", "-t", "--full-tree", commit]);
+    listing.unwrap_or(String::new()).lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '\t');
+            let meta = parts.next().unwrap_or("");
+            parts.next().map(|path| match meta.split_whitespace().nth(1) {
+                Some("tree") => format!("{}:{}/", revision, path),
+                _            => format!("{}:{}", revision, path),
+            })
+        })
+        .collect()
+}
+
 impl Default for GitCompleter {
     fn default() -> GitCompleter {
         GitCompleter {
             base: CompleterBase::new(),
             root: String::from("."),
             max_depth: 32,
+            submodules: Submodules::Recurse,
+            repository: None,
+            resolved: None,
         }
     }
 }
 
 impl GitCompleter {
+    pub fn submodules(&mut self, submodules: Submodules) -> &mut Self {
+        self.submodules = submodules;
+        self
+    }
+
     fn update_root<P: AsRef<Path>>(&mut self, query: P) {
-        let query_root = search_root(&query);
-        self.root = match git_root(query_root) {
-            Ok(root) => root,
-            Err(_)   => path_string(query),
+        let repository = Repository::discover(search_root(&query));
+        self.repository = match self.submodules {
+            Submodules::Current => repository,
+            _                   => repository.map(Repository::outermost),
+        };
+        self.root = match self.repository {
+            Some(ref repository) => path_string(&repository.work_tree),
+            None                 => path_string(query),
         }
     }
+
+    fn resolve_commit(&mut self, revision: &str) -> Option<String> {
+        let key = format!("{}/{}", self.root, revision);
+        if let Some((ref resolved, at, ref commit)) = self.resolved {
+            if *resolved == key && at.elapsed() < DISCOVERY_TTL {
+                return commit.clone()
+            }
+        }
+        let commit = resolve_revision(&*self.root, revision);
+        self.resolved = Some((key, Instant::now(), commit.clone()));
+        commit
+    }
+
+    fn complete_revision<F: Filter>(&mut self, query: &str) -> Option<Vec<WeightedMatch>> {
+        let revision = match split_revision(query) {
+            Some((revision, _)) => revision.to_string(),
+            None => return None,
+        };
+        let root = self.root.clone();
+        self.resolve_commit(&*revision).map(|commit| {
+            let key = format!("{}/{}@{}", root, revision, commit);
+            self.base.matches::<F, _>(query, &*key, || walk_revision(&*root, &*revision, &*commit))
+        })
+    }
 }
 
 impl Completer for GitCompleter {
@@ -62,11 +176,91 @@ impl Completer for GitCompleter {
         "git".to_string()
     }
 
-    fn complete<F: Filter>(&mut self, query: &str) -> RingBuffer<String> {
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
         self.update_root(query);
-        let root = self.root.clone() + "/";
-        let depth = self.max_depth;
+        if let Some(completions) = self.complete_revision::<F>(query) {
+            return completions
+        }
+
+        let (depth, submodules) = (self.max_depth, self.submodules);
+        let key = format!("{}/#{:?}", self.root, submodules);
+
+        match self.repository {
+            Some(ref repository) => self.base.matches::<F, _>(&*query, &*key, || {
+                walk_repository(repository, depth, submodules)
+            }),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GitCompleter, Submodules, split_revision, walk_repository};
+    use ::completer::Completer;
+    use ::filter::SpacedFilter;
+    use ::repository::Repository;
+    use ::util::{fixture, path_string};
+    use std::fs;
+    use std::path::Path;
+
+    fn git_dir<P: AsRef<Path>>(path: P) {
+        fs::create_dir_all(path.as_ref().join("objects")).unwrap();
+        fs::write(path.as_ref().join("HEAD"), "ref: refs/heads/master\n").unwrap();
+    }
+
+    #[test]
+    fn test_split_revision() {
+        assert_eq!(split_revision("master:src/ma"), Some(("master", "src/ma")));
+        assert_eq!(split_revision("origin/dev:"), Some(("origin/dev", "")));
+        assert_eq!(split_revision("HEAD~2:a:b"), Some(("HEAD~2", "a:b")));
+    }
+
+    #[test]
+    fn test_split_revision_ignores_paths() {
+        assert_eq!(split_revision("src/main.rs"), None);
+        assert_eq!(split_revision(":src"), None);
+        assert_eq!(split_revision("/tmp/a:b"), None);
+        assert_eq!(split_revision("~/a:b"), None);
+    }
+
+    #[test]
+    fn test_walk_submodules() {
+        let root = fixture("git-submodules");
+        git_dir(root.join(".git"));
+        git_dir(root.join(".git/modules/vendor"));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("vendor")).unwrap();
+        fs::write(root.join("vendor/.git"), "gitdir: ../.git/modules/vendor\n").unwrap();
+        fs::write(root.join(".gitmodules"), "[submodule \"vendor\"]\n\tpath = vendor\n").unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        fs::write(root.join("vendor/lib.rs"), "").unwrap();
+
+        let repository = Repository::discover(&root).unwrap();
+        let walk = |submodules| walk_repository(&repository, 32, submodules);
+        let path = |relative: &str| path_string(root.join(relative));
+
+        let recursed = walk(Submodules::Recurse);
+        assert!(recursed.contains(&path("src/main.rs")));
+        assert!(recursed.contains(&path("vendor/lib.rs")));
+
+        let roots = walk(Submodules::RootsOnly);
+        assert!(roots.contains(&path("src/main.rs")));
+        assert!(roots.contains(&format!("{}/", path("vendor"))));
+        assert!(!roots.contains(&path("vendor/lib.rs")));
+
+        let vendor = Repository::discover(root.join("vendor")).unwrap();
+        assert_eq!(vendor.work_tree, root.join("vendor"));
+        let current = walk_repository(&vendor, 32, Submodules::Current);
+        assert!(current.contains(&path("vendor/lib.rs")));
+        assert!(!current.contains(&path("src/main.rs")));
 
-        self.base.complete::<F, _>(&*query, &*root, || { walk_dir_ignore(&*root, depth) })
+        let mut completer = GitCompleter::default();
+        completer.submodules(Submodules::Current);
+        let matched: Vec<_> = completer.matches::<SpacedFilter>(&format!("{}/", path("vendor"))).into_iter()
+            .map(|m| m.original)
+            .collect();
+        assert!(matched.contains(&path("vendor/lib.rs")), "{:?}", matched);
+        assert!(!matched.iter().any(|file| file.starts_with(&path("src"))), "{:?}", matched);
     }
 }
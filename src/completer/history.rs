@@ -0,0 +1,306 @@
+use ::completer::Completer;
+use ::filter::{Filter, WeightedMatch};
+use ::frecency::{frecency, now, rank};
+use ::util::canonicalize;
+use std::cmp::max;
+use std::collections::{HashMap, HashSet};
+use std::env::{self, home_dir};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// zsh stores bytes that clash with its tokens as this marker followed by
+/// the byte xor 32
+const ZSH_META: u8 = 0x83;
+
+/// Shown in place of line breaks, since the dropdown renders a single line
+/// per candidate
+const LINE_BREAK: &str = "⏎";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub count: u32,
+    pub last_used: u64,
+}
+
+/// Completes whole command lines from the shell history files, ranked by
+/// the filter weight combined with each command's frecency
+pub struct HistoryCompleter {
+    files: Vec<PathBuf>,
+    entries: Option<Vec<HistoryEntry>>,
+}
+
+type Parsed = Vec<(String, Option<u64>)>;
+
+/// Parses `~/.bash_history`, where `HISTTIMEFORMAT` prefixes each command
+/// with a `#<timestamp>` line and lines until the next timestamp belong to
+/// the same command
+fn parse_bash(contents: &str) -> Parsed {
+    let mut entries: Parsed = vec![];
+    let mut timestamped = false;
+    for line in contents.lines() {
+        if let Some(timestamp) = line.strip_prefix('#') {
+            if let Ok(timestamp) = timestamp.parse() {
+                entries.push((String::new(), Some(timestamp)));
+                timestamped = true;
+                continue
+            }
+        }
+        let continues = timestamped && entries.last().is_some_and(|entry| entry.1.is_some());
+        if continues {
+            let command = &mut entries.last_mut().unwrap().0;
+            if !command.is_empty() {
+                command.push('\n');
+            }
+            command.push_str(line);
+        } else {
+            entries.push((line.to_string(), None));
+        }
+    }
+    entries
+}
+
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut unmetafied = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == ZSH_META {
+            if let Some(&next) = bytes.next() {
+                unmetafied.push(next ^ 32);
+            }
+        } else {
+            unmetafied.push(byte);
+        }
+    }
+    unmetafied
+}
+
+/// Parses `~/.zsh_history` in both the plain and `EXTENDED_HISTORY`
+/// (`: <start>:<elapsed>;<command>`) formats
+fn parse_zsh(contents: &str) -> Parsed {
+    let mut entries: Parsed = vec![];
+    let mut continued = false;
+    for line in contents.lines() {
+        let ends_escaped = line.ends_with('\\');
+        let text = if ends_escaped { &line[..line.len() - 1] } else { line };
+        if continued {
+            if let Some(&mut (ref mut command, _)) = entries.last_mut() {
+                command.push('\n');
+                command.push_str(text);
+            }
+        } else if line.starts_with(": ") && line.contains(';') {
+            let idx = line.find(';').unwrap();
+            let timestamp = line[2..idx].split(':').next().and_then(|start| start.trim().parse().ok());
+            entries.push((text[idx + 1..].to_string(), timestamp));
+        } else {
+            entries.push((text.to_string(), None));
+        }
+        continued = ends_escaped;
+    }
+    entries
+}
+
+fn unescape_fish(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Parses fish's YAML-like history, `- cmd: <command>` followed by an
+/// indented `when: <timestamp>`
+fn parse_fish(contents: &str) -> Parsed {
+    let mut entries: Parsed = vec![];
+    for line in contents.lines() {
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            entries.push((unescape_fish(command), None));
+        } else if line.trim_start().starts_with("when: ") {
+            if let Some(&mut (_, ref mut timestamp)) = entries.last_mut() {
+                *timestamp = line.trim_start()["when: ".len()..].trim().parse().ok();
+            }
+        }
+    }
+    entries
+}
+
+fn read_history<P: AsRef<Path>>(path: P) -> Option<Parsed> {
+    let mut bytes = vec![];
+    File::open(&path).and_then(|mut file| file.read_to_end(&mut bytes)).ok()?;
+    let name = path.as_ref().file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    Some(if name.contains("zsh") {
+        parse_zsh(&String::from_utf8_lossy(&unmetafy(&bytes)))
+    } else if name.contains("fish") {
+        parse_fish(&String::from_utf8_lossy(&bytes))
+    } else {
+        parse_bash(&String::from_utf8_lossy(&bytes))
+    })
+}
+
+fn modified<P: AsRef<Path>>(path: P) -> u64 {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map(|mtime| mtime.as_secs())
+        .unwrap_or(0)
+}
+
+/// Collapses repeated commands into a single entry. Commands without a
+/// timestamp are assumed to have run one second apart, ending when the
+/// history file was last written.
+fn deduplicate(parsed: Parsed, written: u64, entries: &mut HashMap<String, HistoryEntry>) {
+    let total = parsed.len() as u64;
+    for (idx, (command, timestamp)) in parsed.into_iter().enumerate() {
+        let command = command.trim().to_string();
+        if command.is_empty() {
+            continue
+        }
+        let last_used = timestamp.unwrap_or(written.saturating_sub(total - idx as u64));
+        let entry = entries.entry(command.clone())
+            .or_insert(HistoryEntry { command, count: 0, last_used });
+        entry.count += 1;
+        entry.last_used = max(entry.last_used, last_used);
+    }
+}
+
+impl Default for HistoryCompleter {
+    fn default() -> HistoryCompleter {
+        let mut files: Vec<PathBuf> = env::var_os("HISTFILE").map(PathBuf::from).into_iter().collect();
+        if let Some(home) = home_dir() {
+            files.push(home.join(".bash_history"));
+            files.push(home.join(".zsh_history"));
+            files.push(home.join(".local/share/fish/fish_history"));
+        }
+        HistoryCompleter::new(files)
+    }
+}
+
+fn displayed(command: &str) -> String {
+    command.replace('\n', LINE_BREAK)
+}
+
+impl HistoryCompleter {
+    /// Reads each file once, however many of the paths lead to it
+    pub fn new(mut files: Vec<PathBuf>) -> Self {
+        let mut seen = HashSet::new();
+        files.retain(|file| seen.insert(canonicalize(file)));
+        HistoryCompleter { files, entries: None }
+    }
+
+    pub fn entries(&mut self) -> &Vec<HistoryEntry> {
+        if self.entries.is_none() {
+            let mut entries = HashMap::new();
+            for file in &self.files {
+                if let Some(parsed) = read_history(file) {
+                    deduplicate(parsed, modified(file), &mut entries);
+                }
+            }
+            self.entries = Some(entries.into_values().collect());
+        }
+        self.entries.as_ref().unwrap()
+    }
+}
+
+impl Completer for HistoryCompleter {
+    fn label(&self) -> String {
+        "history".to_string()
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        let now = now();
+        let mut completions: Vec<_> = self.entries().iter()
+            .filter_map(|entry| F::matched(query, &displayed(&entry.command)).map(|m| WeightedMatch {
+                weight: rank(m.weight, frecency(entry.count, entry.last_used, now)),
+                ..m
+            }))
+            .collect();
+
+        completions.sort_by(WeightedMatch::cmp);
+        completions
+    }
+
+    fn resolve(&self, selected: &str) -> String {
+        if !selected.contains(LINE_BREAK) {
+            return selected.to_string()
+        }
+        self.entries.iter().flat_map(|entries| entries.iter())
+            .find(|entry| displayed(&entry.command) == selected)
+            .map(|entry| entry.command.clone())
+            .unwrap_or_else(|| selected.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HistoryCompleter, parse_bash, parse_zsh, parse_fish, unmetafy};
+    use ::completer::Completer;
+    use ::filter::SpacedFilter;
+    use ::readkeys::Printable;
+    use ::util::fixture;
+    use std::fs;
+
+    #[test]
+    fn test_parse_bash_plain() {
+        let parsed = parse_bash("ls\ncd src\n");
+        assert_eq!(parsed, vec![("ls".to_string(), None), ("cd src".to_string(), None)]);
+    }
+
+    #[test]
+    fn test_parse_bash_timestamped() {
+        let parsed = parse_bash("#1500000000\nls\n#1500000010\nfor i in a; do\necho $i\ndone\n");
+        assert_eq!(parsed, vec![
+            ("ls".to_string(), Some(1500000000)),
+            ("for i in a; do\necho $i\ndone".to_string(), Some(1500000010)),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_zsh_extended() {
+        let parsed = parse_zsh(": 1500000000:0;git status\n: 1500000005:2;echo a \\\nb\nls\n");
+        assert_eq!(parsed, vec![
+            ("git status".to_string(), Some(1500000000)),
+            ("echo a \nb".to_string(), Some(1500000005)),
+            ("ls".to_string(), None),
+        ]);
+    }
+
+    #[test]
+    fn test_unmetafy() {
+        assert_eq!(unmetafy(&[b'a', 0x83, 0xa3 ^ 32, b'b']), vec![b'a', 0xa3, b'b']);
+    }
+
+    #[test]
+    fn test_multi_line_commands() {
+        let root = fixture("history-multi-line");
+        let file = root.join(".bash_history");
+        fs::write(&file, "#1500000000\nfor i in a; do\necho $i\ndone\n#1500000010\nls\n").unwrap();
+
+        let mut completer = HistoryCompleter::new(vec![file.clone(), root.join("./.bash_history"), file]);
+        assert!(completer.entries().iter().all(|entry| entry.count == 1));
+
+        let completions = completer.complete::<SpacedFilter>("for");
+        let shown = completions.iter().next().unwrap().without_escape_codes();
+        assert_eq!(shown, "for i in a; do⏎echo $i⏎done");
+        assert_eq!(completer.resolve(&shown), "for i in a; do\necho $i\ndone");
+        assert_eq!(completer.resolve("ls"), "ls");
+    }
+
+    #[test]
+    fn test_parse_fish() {
+        let parsed = parse_fish("- cmd: echo \\\\o/\n  when: 1500000000\n  paths:\n    - o\n- cmd: ls\n");
+        assert_eq!(parsed, vec![
+            ("echo \\o/".to_string(), Some(1500000000)),
+            ("ls".to_string(), None),
+        ]);
+    }
+}
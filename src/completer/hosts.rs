@@ -0,0 +1,204 @@
+use ::completer::Completer;
+use ::filter::{Filter, WeightedMatch};
+use ::util::{describe, expand_user, path_string, read_file};
+use glob::glob;
+use std::env::home_dir;
+use std::path::{Path, PathBuf};
+
+/// ssh refuses to nest `Include` deeper than this
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Host {
+    pub name: String,
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+}
+
+/// Completes host names from the ssh client configuration and known hosts
+#[derive(Default)]
+pub struct HostCompleter {
+    hosts: Option<Vec<Host>>,
+}
+
+fn is_pattern(host: &str) -> bool {
+    host.contains(['*', '?', '!'])
+}
+
+/// Splits a config line into its lowercased keyword and its arguments.
+/// ssh allows either whitespace or `=` between the two.
+fn keyword(line: &str) -> Option<(String, &str)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None
+    }
+    let idx = line.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(line.len());
+    let arguments = line[idx..].trim_start_matches(|c: char| c.is_whitespace() || c == '=');
+    Some((line[..idx].to_lowercase(), arguments.trim()))
+}
+
+fn unquote(value: &str) -> String {
+    value.trim_matches('"').to_string()
+}
+
+fn add_host(hosts: &mut Vec<Host>, name: &str) -> usize {
+    match hosts.iter().position(|host| host.name == name) {
+        Some(idx) => idx,
+        None => {
+            hosts.push(Host { name: name.to_string(), ..Host::default() });
+            hosts.len() - 1
+        }
+    }
+}
+
+/// Parses ssh_config contents, following `Include` relative to `base`
+fn parse_config(contents: &str, base: &Path, depth: usize, hosts: &mut Vec<Host>) {
+    let mut current: Vec<usize> = vec![];
+    for (keyword, arguments) in contents.lines().filter_map(keyword) {
+        match &*keyword {
+            "host" => {
+                current = arguments.split_whitespace()
+                    .map(unquote)
+                    .filter(|name| !is_pattern(name))
+                    .map(|name| add_host(hosts, &name))
+                    .collect();
+            }
+            "match" => current = vec![],
+            // Like ssh, the first value obtained for each option wins
+            "hostname" => for &idx in &current {
+                hosts[idx].hostname = hosts[idx].hostname.take().or_else(|| Some(unquote(arguments)));
+            },
+            "user" => for &idx in &current {
+                hosts[idx].user = hosts[idx].user.take().or_else(|| Some(unquote(arguments)));
+            },
+            "include" if depth < MAX_INCLUDE_DEPTH => {
+                for pattern in arguments.split_whitespace().map(unquote) {
+                    let pattern = base.join(expand_user(pattern));
+                    let mut paths: Vec<PathBuf> = match glob(&path_string(&pattern)) {
+                        Ok(paths) => paths.filter_map(|path| path.ok()).collect(),
+                        Err(_) => continue,
+                    };
+                    paths.sort();
+                    for path in paths {
+                        parse_config(&read_file(&path), base, depth + 1, hosts);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Host names from known_hosts, skipping hashed entries and markers and
+/// dropping the brackets and port from `[host]:port`
+fn parse_known_hosts(contents: &str) -> Vec<String> {
+    let mut names = vec![];
+    for line in contents.lines().map(str::trim) {
+        let mut fields = line.split_whitespace();
+        let mut field = fields.next();
+        if field.is_some_and(|f| f.starts_with('@')) {
+            field = fields.next();
+        }
+        let patterns = match field {
+            Some(patterns) if !patterns.starts_with('#') && !patterns.starts_with('|') => patterns,
+            _ => continue,
+        };
+        for name in patterns.split(',') {
+            let name = match name.strip_prefix('[') {
+                Some(bracketed) => bracketed.split(']').next().unwrap_or(""),
+                None => name,
+            };
+            if !name.is_empty() && !is_pattern(name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+fn load_hosts() -> Vec<Host> {
+    let mut hosts = vec![];
+    let home_ssh = home_dir().map(|home| home.join(".ssh"));
+
+    if let Some(ref ssh) = home_ssh {
+        parse_config(&read_file(ssh.join("config")), ssh, 0, &mut hosts);
+    }
+    let system = Path::new("/etc/ssh");
+    parse_config(&read_file(system.join("ssh_config")), system, 0, &mut hosts);
+
+    let known_hosts = home_ssh.iter().map(|ssh| ssh.join("known_hosts"))
+        .chain(Some(system.join("ssh_known_hosts")));
+    for path in known_hosts {
+        for name in parse_known_hosts(&read_file(path)) {
+            add_host(&mut hosts, &name);
+        }
+    }
+    hosts
+}
+
+fn format_host(host: &Host) -> String {
+    match (&host.user, &host.hostname) {
+        (Some(user), Some(hostname)) => describe(&host.name, format!("{}@{}", user, hostname)),
+        (Some(user), None)           => describe(&host.name, format!("{}@", user)),
+        (None, Some(hostname))       => describe(&host.name, hostname),
+        (None, None)                 => host.name.clone(),
+    }
+}
+
+impl HostCompleter {
+    pub fn new(hosts: Vec<Host>) -> Self {
+        HostCompleter { hosts: Some(hosts) }
+    }
+
+    pub fn hosts(&mut self) -> &Vec<Host> {
+        if self.hosts.is_none() {
+            self.hosts = Some(load_hosts());
+        }
+        self.hosts.as_ref().unwrap()
+    }
+}
+
+impl Completer for HostCompleter {
+    fn label(&self) -> String {
+        "hosts".to_string()
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        let candidates: Vec<_> = self.hosts().iter().map(format_host).collect();
+        let mut completions: Vec<_> = candidates.iter()
+            .filter_map(|candidate| F::matched_candidate(query, candidate))
+            .collect();
+
+        completions.sort_by(WeightedMatch::cmp);
+        completions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_config, parse_known_hosts, Host};
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_config() {
+        let config = "Host web web2\n  HostName web.example.com\n  User=deploy\n\
+                      Host *.internal !bastion\n  User nobody\n\
+                      Host web\n  HostName ignored\n\
+                      Match user root\n  HostName ignored\n";
+        let mut hosts = vec![];
+        parse_config(config, Path::new("/nonexistent"), 0, &mut hosts);
+        assert_eq!(hosts, vec![
+            Host { name: "web".to_string(), hostname: Some("web.example.com".to_string()), user: Some("deploy".to_string()) },
+            Host { name: "web2".to_string(), hostname: Some("web.example.com".to_string()), user: Some("deploy".to_string()) },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_known_hosts() {
+        let known_hosts = "a.example.com,10.0.0.1 ssh-rsa AAAA\n\
+                           |1|hashed= ssh-rsa AAAA\n\
+                           @cert-authority *.example.com ssh-rsa AAAA\n\
+                           [b.example.com]:2222 ssh-ed25519 AAAA\n";
+        assert_eq!(parse_known_hosts(known_hosts), vec!["a.example.com", "10.0.0.1", "b.example.com"]);
+    }
+}
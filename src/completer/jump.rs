@@ -0,0 +1,84 @@
+use ::completer::{Completer, MixedCompleter, Mode};
+use ::filter::{Filter, WeightedMatch};
+use ::frecency::{Database, frecency, now, rank};
+use ::util::path_string;
+
+/// Completes directories from anywhere on disk that were visited before,
+/// ranked by match weight and frecency. Queries that look like paths, or
+/// that match nothing in the database, fall back to the filesystem modes.
+pub struct JumpCompleter {
+    database: Database,
+    fallback: MixedCompleter,
+    jump: bool,
+    fell_back: bool,
+}
+
+impl Default for JumpCompleter {
+    fn default() -> JumpCompleter {
+        JumpCompleter::new(Database::open(), MixedCompleter::default())
+    }
+}
+
+fn is_path(query: &str) -> bool {
+    query.starts_with('/') || query.starts_with('.') || query.starts_with('~')
+}
+
+impl JumpCompleter {
+    pub fn new(database: Database, fallback: MixedCompleter) -> Self {
+        JumpCompleter { database, fallback, jump: true, fell_back: false }
+    }
+
+    fn complete_jump<F: Filter>(&self, query: &str) -> Vec<WeightedMatch> {
+        let now = now();
+        let mut completions: Vec<_> = self.database.entries().into_iter()
+            .filter_map(|entry| {
+                let path = format!("{}/", path_string(&entry.path));
+                F::matched(query, &path).map(|m| WeightedMatch {
+                    weight: rank(m.weight, frecency(entry.count, entry.last_used, now)),
+                    ..m
+                })
+            })
+            .collect();
+
+        completions.sort_by(WeightedMatch::cmp);
+        completions
+    }
+}
+
+impl Completer for JumpCompleter {
+    fn label(&self) -> String {
+        match (self.jump, self.fell_back) {
+            (true, false) => "jump".to_string(),
+            (true, true)  => format!("jump [{}]", self.fallback.label()),
+            (false, _)    => self.fallback.label(),
+        }
+    }
+
+    fn completes_paths(&self) -> bool {
+        true
+    }
+
+    /// Cycles from jumping through each of the fallback's modes and back
+    fn toggle_mode(&mut self) {
+        if self.jump {
+            self.jump = false;
+            self.fallback.mode(Mode::Recursive);
+        } else if *self.fallback.current_mode() == Mode::Auto {
+            self.jump = true;
+        } else {
+            self.fallback.toggle_mode();
+        }
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        self.fell_back = false;
+        if self.jump && !query.is_empty() && !is_path(query) {
+            let completions = self.complete_jump::<F>(query);
+            if !completions.is_empty() {
+                return completions
+            }
+        }
+        self.fell_back = self.jump;
+        self.fallback.matches::<F>(query)
+    }
+}
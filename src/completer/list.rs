@@ -1,29 +1,137 @@
 use ::completer::Completer;
-use ::ring_buffer::RingBuffer;
 use ::filter::{Filter, WeightedMatch};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
 
 pub struct ListCompleter {
-    choices: Vec<String>
+    choices: Vec<String>,
+    seen: HashSet<String>,
+    incoming: Option<Receiver<String>>,
+}
+
+/// Reads `delimiter` separated choices on a background thread so the
+/// picker can start before the input is exhausted
+pub fn read_choices<R: Read + Send + 'static>(reader: R, delimiter: u8) -> Receiver<String> {
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut buffer = vec![];
+        loop {
+            buffer.clear();
+            match reader.read_until(delimiter, &mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => (),
+            }
+            if buffer.last() == Some(&delimiter) {
+                buffer.pop();
+            }
+            if delimiter == b'\n' && buffer.last() == Some(&b'\r') {
+                buffer.pop();
+            }
+            if !buffer.is_empty() && tx.send(String::from_utf8_lossy(&buffer).to_string()).is_err() {
+                break
+            }
+        }
+    });
+    rx
 }
 
 impl ListCompleter {
+    /// Drops repeated choices, which compspecs commonly produce, keeping
+    /// the first of each
     pub fn new(choices: Vec<String>) -> Self {
-        ListCompleter { choices }
+        let mut completer = ListCompleter { choices: vec![], seen: HashSet::new(), incoming: None };
+        completer.extend(choices);
+        completer
+    }
+
+    /// Takes choices as they arrive on `incoming`
+    pub fn from_receiver(incoming: Receiver<String>) -> Self {
+        ListCompleter { choices: vec![], seen: HashSet::new(), incoming: Some(incoming) }
+    }
+
+    fn extend<I: IntoIterator<Item=String>>(&mut self, choices: I) {
+        for choice in choices {
+            if self.seen.insert(choice.clone()) {
+                self.choices.push(choice);
+            }
+        }
     }
 }
 
 impl Completer for ListCompleter {
     fn label(&self) -> String {
-        "list".to_string()
+        if self.streaming() {
+            format!("list [{}…]", self.choices.len())
+        } else {
+            "list".to_string()
+        }
+    }
+
+    fn streaming(&self) -> bool {
+        self.incoming.is_some()
+    }
+
+    fn poll(&mut self) -> bool {
+        let mut received = vec![];
+        let mut disconnected = false;
+        if let Some(ref incoming) = self.incoming {
+            loop {
+                match incoming.try_recv() {
+                    Ok(choice) => received.push(choice),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => { disconnected = true; break },
+                }
+            }
+        }
+        if disconnected {
+            self.incoming = None;
+        }
+        let before = self.choices.len();
+        self.extend(received);
+        disconnected || self.choices.len() > before
     }
 
-    fn complete<F: Filter>(&mut self, query: &str) -> RingBuffer<String> {
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        self.poll();
         let mut completions: Vec<_> = self.choices.iter()
-            .filter_map(|p| F::matched(query, &*p))
+            .filter_map(|p| F::matched_candidate(query, &*p))
             .collect();
 
         completions.sort_by(WeightedMatch::cmp);
-        let completions = completions.into_iter().map(|m| m.result).collect();
-        RingBuffer::from_vec(completions)
+        completions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ListCompleter, read_choices};
+    use ::completer::Completer;
+    use ::filter::SpacedFilter;
+    use ::readkeys::Printable;
+
+    fn complete(completer: &mut ListCompleter, query: &str) -> Vec<String> {
+        completer.complete::<SpacedFilter>(query).iter()
+            .map(|completion| completion.without_escape_codes())
+            .collect()
+    }
+
+    #[test]
+    fn test_drops_repeated_choices() {
+        let choices = vec!["--all", "--help", "--all", "-a"].into_iter().map(str::to_string).collect();
+        let mut completer = ListCompleter::new(choices);
+        let mut completions = complete(&mut completer, "");
+        completions.sort();
+        assert_eq!(completions, vec!["--all", "--help", "-a"]);
+    }
+
+    #[test]
+    fn test_read_choices() {
+        let lines: Vec<_> = read_choices(&b"one\r\ntwo\n\nthree"[..], b'\n').iter().collect();
+        assert_eq!(lines, vec!["one", "two", "three"]);
+        let names: Vec<_> = read_choices(&b"a file\nname\0\0b\0"[..], b'\0').iter().collect();
+        assert_eq!(names, vec!["a file\nname", "b"]);
     }
 }
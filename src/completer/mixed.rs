@@ -1,18 +1,23 @@
-use ::completer::{Completer, GitCompleter, RecursiveCompleter};
-use ::ring_buffer::RingBuffer;
-use ::filter::Filter;
+use ::completer::{Completer, ChangedCompleter, GitCompleter, RecursiveCompleter, Submodules};
+use ::completer::{Accounts, UserCompleter, VariableCompleter, is_home, is_variable};
+use ::filter::{Filter, WeightedMatch};
 use ::util::{git_root, search_root, path_string, canonicalize};
 use std::path::Path;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Mode {
     Git,
     Recursive,
+    Changed,
     Auto,
 }
 
 pub struct MixedCompleter {
     git: GitCompleter,
     recursive: RecursiveCompleter,
+    changed: ChangedCompleter,
+    variables: VariableCompleter,
+    homes: UserCompleter,
     mode:  Mode,
     root: String,
 }
@@ -22,6 +27,9 @@ impl Default for MixedCompleter {
         MixedCompleter {
             git: GitCompleter::default(),
             recursive: RecursiveCompleter::default(),
+            changed: ChangedCompleter::default(),
+            variables: VariableCompleter::default(),
+            homes: UserCompleter::new(Accounts::Homes),
             mode: Mode::Auto,
             root: String::from("."),
         }
@@ -34,24 +42,51 @@ impl MixedCompleter {
         self
     }
 
-    fn complete_git<F: Filter>(&mut self, query: &str) -> RingBuffer<String> {
+    pub fn current_mode(&self) -> &Mode {
+        &self.mode
+    }
+
+    pub fn submodules(&mut self, submodules: Submodules) -> &mut Self {
+        self.git.submodules(submodules);
+        self
+    }
+
+    pub fn base_branch<S: Into<String>>(&mut self, base_branch: S) -> &mut Self {
+        self.changed.base_branch(base_branch);
+        self
+    }
+
+    pub fn shell_variables(&mut self, names: Vec<String>) -> &mut Self {
+        self.variables.shell_variables(names);
+        self
+    }
+
+    fn complete_git<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
         if self.git_allowed() {
-            self.git.complete::<F>(&*query)
+            self.git.matches::<F>(&*query)
         } else {
-            RingBuffer::from_vec(vec![])
+            vec![]
         }
     }
 
-    fn complete_auto<F: Filter>(&mut self, query: &str) -> RingBuffer<String> {
+    fn complete_changed<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        if self.git_allowed() {
+            self.changed.matches::<F>(&*query)
+        } else {
+            vec![]
+        }
+    }
+
+    fn complete_auto<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
         if self.git_allowed() {
             self.complete_git::<F>(query)
         } else {
-            self.recursive.complete::<F>(query)
+            self.recursive.matches::<F>(query)
         }
     }
 
-    fn complete_recursive<F: Filter>(&mut self, query: &str) -> RingBuffer<String> {
-        self.recursive.complete::<F>(query)
+    fn complete_recursive<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        self.recursive.matches::<F>(query)
     }
 
     fn git_allowed(&self) -> bool {
@@ -70,18 +105,31 @@ impl Completer for MixedCompleter {
             Mode::Auto      => if self.git_allowed() { "auto [git]" } else { "auto [rec]" },
             Mode::Git       => "git",
             Mode::Recursive => "recursive",
+            Mode::Changed   => "changed",
         }.to_string()
     }
 
+    fn completes_paths(&self) -> bool {
+        true
+    }
+
     fn toggle_mode(&mut self) {
         self.mode = match self.mode {
             Mode::Recursive => Mode::Git,
-            Mode::Git       => Mode::Auto,
+            Mode::Git       => Mode::Changed,
+            Mode::Changed   => Mode::Auto,
             Mode::Auto      => Mode::Recursive,
         };
     }
 
-    fn complete<F: Filter>(&mut self, query: &str) -> RingBuffer<String> {
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        if is_variable(query) {
+            return self.variables.matches::<F>(query)
+        }
+        if is_home(query) {
+            return self.homes.matches::<F>(query)
+        }
+
         self.update_root(query);
         let query = &*path_string(canonicalize(query));
 
@@ -89,6 +137,7 @@ impl Completer for MixedCompleter {
             Mode::Auto      => self.complete_auto::<F>(query),
             Mode::Git       => self.complete_git::<F>(query),
             Mode::Recursive => self.complete_recursive::<F>(query),
+            Mode::Changed   => self.complete_changed::<F>(query),
         }
     }
  }
@@ -2,27 +2,74 @@ use ::filter::{Filter, WeightedMatch};
 use ::ring_buffer::RingBuffer;
 use rayon::prelude::*;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 pub trait Completer {
-    fn complete<F: Filter>(&mut self, query: &str) -> RingBuffer<String>;
+    /// Candidates matching `query` best first, with the weight and matched
+    /// positions each was ranked by
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch>;
+
+    fn complete<F: Filter>(&mut self, query: &str) -> RingBuffer<String> {
+        RingBuffer::from_vec(self.matches::<F>(query).into_iter().map(|m| m.result).collect())
+    }
 
     fn toggle_mode(&mut self) {}
     fn label(&self) -> String;
+
+    /// Whether candidates are still arriving, so the prompt should keep
+    /// redrawing while it waits for keys
+    fn streaming(&self) -> bool { false }
+
+    /// Takes in candidates that arrived since the last call, returning
+    /// whether anything changed
+    fn poll(&mut self) -> bool { false }
+
+    /// Maps the selected candidate, as displayed, to the text it inserts
+    fn resolve(&self, selected: &str) -> String { selected.to_string() }
+
+    /// Whether candidates are paths, so accepted directories are worth
+    /// remembering for jump completion
+    fn completes_paths(&self) -> bool { false }
 }
 
 mod recursive;
 mod git;
 mod mixed;
 mod list;
+mod changed;
+mod command;
+mod history;
+mod hosts;
+mod jump;
+mod options;
+mod plugin;
+mod processes;
+mod remote;
+mod targets;
+mod users;
+mod variables;
 
-pub use self::git::GitCompleter;
+pub use self::git::{GitCompleter, Submodules};
 pub use self::recursive::RecursiveCompleter;
-pub use self::list::ListCompleter;
-pub use self::mixed::MixedCompleter;
+pub use self::list::{ListCompleter, read_choices};
+pub use self::mixed::{MixedCompleter, Mode};
+pub use self::changed::ChangedCompleter;
+pub use self::command::CommandCompleter;
+pub use self::history::{HistoryCompleter, HistoryEntry};
+pub use self::hosts::{Host, HostCompleter};
+pub use self::jump::JumpCompleter;
+pub use self::options::{Described, OptionCompleter, Spec};
+pub use self::plugin::{Context, PluginCompleter};
+pub use self::processes::{Process, ProcessCompleter};
+pub use self::remote::RemoteCompleter;
+pub use self::targets::{Target, TargetCompleter};
+pub use self::users::{Accounts, UserCompleter, is_home};
+pub use self::variables::{VariableCompleter, is_variable};
 
 
 pub struct CompleterBase {
-    cache: HashMap<String, Vec<String>>
+    /// Candidates by the key they were listed under, and when they were listed
+    cache: HashMap<String, (Instant, Vec<String>)>
 }
 
 
@@ -36,26 +83,31 @@ impl CompleterBase {
     {
         if !self.cache.contains_key(root) {
             let paths = f();
-            self.cache.insert(root.to_string(), paths);
+            self.cache.insert(root.to_string(), (Instant::now(), paths));
         }
-        &self.cache[root]
+        &self.cache[root].1
     }
 
-    pub fn complete<F, G>(&mut self, query: &str, root: &str, completer: G)
-                          -> RingBuffer<String>
+    /// Drops everything listed longer than `age` ago, so files created or
+    /// removed since show up the next time they're looked up
+    pub fn expire(&mut self, age: Duration) {
+        self.cache.retain(|_, &mut (listed, _)| listed.elapsed() < age);
+    }
+
+    /// Drops the candidates cached for `root` so the next lookup lists them again
+    pub fn forget(&mut self, root: &str) {
+        self.cache.remove(root);
+    }
+
+    pub fn matches<F, G>(&mut self, query: &str, root: &str, completer: G) -> Vec<WeightedMatch>
         where G: FnOnce() -> Vec<String>, F: Filter
     {
         let mut completions: Vec<_> = self.cache(&*root, completer).par_iter()
             .map(|p| p.replace("./", ""))
-            .filter_map(|p| F::matched(query, &*p))
+            .filter_map(|p| F::matched_candidate(query, &*p))
             .collect();
 
         completions.sort_by(WeightedMatch::cmp);
-
-        let results = completions.into_iter()
-            .map(|comp| comp.result.to_string())
-            .collect();
-
-        RingBuffer::from_vec(results)
+        completions
     }
 }
@@ -0,0 +1,415 @@
+use ::completer::Completer;
+use ::filter::{Filter, WeightedMatch};
+use ::util::{cache_dir, describe, path_string, read_file, which};
+use flate2::read::GzDecoder;
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// How long `<command> --help` may run before it's killed
+const HELP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A flag or subcommand and its description
+pub type Described = (String, String);
+
+/// Options and subcommands of a program
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Spec {
+    pub flags: Vec<Described>,
+    pub subcommands: Vec<Described>,
+}
+
+/// Completes a command's flags, or its subcommands for queries that don't
+/// start with `-`. Specs are scraped from the local man page, falling back
+/// to `--help` for commands allowed to run it, and cached per binary path
+/// and modification time.
+pub struct OptionCompleter {
+    command: String,
+    run_help: bool,
+    spec: Option<Spec>,
+}
+
+/// Splits a help line into the option column and the description after
+/// the first run of two or more spaces
+fn split_columns(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    let gap = line.find("  ").or_else(|| line.find('\t'));
+    match gap {
+        Some(idx) => (line[..idx].trim(), line[idx..].trim()),
+        None => (line, ""),
+    }
+}
+
+/// The flag names in an option column such as `-o, --output=<FILE>`
+fn flag_names(column: &str) -> Vec<String> {
+    column.split([',', ' ', '|'])
+        .map(str::trim)
+        .filter(|token| token.starts_with('-') && token.len() > 1)
+        .map(|token| token.split(['=', '[', '<']).next().unwrap_or("").to_string())
+        .filter(|flag| flag.len() > 1 && flag != "--")
+        .collect()
+}
+
+fn push_flags(spec: &mut Spec, column: &str, description: &str) {
+    for flag in flag_names(column) {
+        if !spec.flags.iter().any(|existing| existing.0 == flag) {
+            spec.flags.push((flag, description.to_string()));
+        }
+    }
+}
+
+/// Parses `--help` output: indented lines starting with `-` are options,
+/// and indented words under a heading mentioning commands are subcommands
+fn parse_help(help: &str) -> Spec {
+    let mut spec = Spec::default();
+    let mut in_commands = false;
+    let mut pending: Option<String> = None;
+
+    for line in help.lines() {
+        let indented = line.starts_with(|c: char| c.is_whitespace());
+        if !indented && !line.trim().is_empty() {
+            in_commands = line.trim_end().ends_with(':') && line.to_lowercase().contains("command");
+            pending = None;
+            continue
+        }
+
+        let (column, description) = split_columns(line);
+        if column.starts_with('-') {
+            // Descriptions sometimes start on the next line
+            pending = if description.is_empty() { Some(column.to_string()) } else { None };
+            push_flags(&mut spec, column, description);
+        } else if let Some(column) = pending.take() {
+            let description = line.trim();
+            for flag in flag_names(&column) {
+                if let Some(existing) = spec.flags.iter_mut().find(|existing| existing.0 == flag) {
+                    existing.1 = description.to_string();
+                }
+            }
+        } else if in_commands && !column.is_empty() && !column.contains(' ') {
+            spec.subcommands.push((column.to_string(), description.to_string()));
+        }
+    }
+    spec
+}
+
+/// Removes font changes and common escapes from a roff line
+fn strip_roff(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue
+        }
+        match chars.next() {
+            Some('f') => { chars.next(); },
+            Some('(') => { chars.next(); chars.next(); text.push('-'); },
+            Some('&') | Some('/') | Some(',') => (),
+            Some('e') => text.push('\\'),
+            Some(other) => text.push(other),
+            None => (),
+        }
+    }
+    text
+}
+
+/// Drops the macro from a line like `.B \-\-all`
+fn macro_arguments(line: &str) -> String {
+    if line.starts_with('.') {
+        line.split_once(' ').map(|(_, arguments)| arguments).unwrap_or("").to_string()
+    } else {
+        line.to_string()
+    }
+}
+
+/// The first, possibly quoted, argument of a macro
+fn first_argument(arguments: &str) -> String {
+    let arguments = arguments.trim();
+    if let Some(quoted) = arguments.strip_prefix('"') {
+        quoted.split('"').next().unwrap_or("").to_string()
+    } else {
+        arguments.split_whitespace().next().unwrap_or("").to_string()
+    }
+}
+
+/// Parses the option lists of a man page's roff source, where each option
+/// is tagged by `.TP` followed by the tag line, or by `.IP "<tag>"`
+fn parse_man(roff: &str) -> Spec {
+    let mut spec = Spec::default();
+    let mut lines = roff.lines().peekable();
+    while let Some(line) = lines.next() {
+        let tag = if line.starts_with(".TP") {
+            lines.next().map(macro_arguments)
+        } else {
+            line.strip_prefix(".IP ").map(first_argument)
+        };
+
+        let tag = match tag {
+            Some(tag) => strip_roff(&tag),
+            None => continue,
+        };
+        if !tag.trim_start().starts_with('-') {
+            continue
+        }
+        let description = match lines.peek() {
+            Some(next) if !next.starts_with('.') => strip_roff(next),
+            _ => String::new(),
+        };
+        push_flags(&mut spec, &tag, description.trim());
+    }
+    spec
+}
+
+fn man_dirs() -> Vec<PathBuf> {
+    let manpath = env::var_os("MANPATH").map(|manpath| env::split_paths(&manpath).collect::<Vec<_>>());
+    manpath.unwrap_or_default()
+        .into_iter()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .chain(vec![PathBuf::from("/usr/local/share/man"), PathBuf::from("/usr/share/man")])
+        .collect()
+}
+
+fn read_man_page(command: &str) -> Option<String> {
+    for dir in man_dirs() {
+        for section in &["1", "8", "6"] {
+            let page = dir.join(format!("man{}", section)).join(format!("{}.{}", command, section));
+            let mut roff = String::new();
+            if let Ok(file) = File::open(page.with_extension(format!("{}.gz", section))) {
+                if GzDecoder::new(file).read_to_string(&mut roff).is_ok() {
+                    return Some(roff)
+                }
+            }
+            if let Ok(mut file) = File::open(&page) {
+                if file.read_to_string(&mut roff).is_ok() {
+                    return Some(roff)
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Runs `<binary> --help`, killing it if it doesn't finish in time
+fn read_help(binary: &Path) -> Option<String> {
+    let mut child = Command::new(binary).arg("--help")
+        .env("PAGER", "cat")
+        .stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        .spawn().ok()?;
+
+    // Drain the pipes while waiting so long help texts can't fill them
+    let (mut stdout, mut stderr) = (child.stdout.take()?, child.stderr.take()?);
+    let stdout = thread::spawn(move || { let mut out = String::new(); let _ = stdout.read_to_string(&mut out); out });
+    let stderr = thread::spawn(move || { let mut err = String::new(); let _ = stderr.read_to_string(&mut err); err });
+
+    let start = Instant::now();
+    while child.try_wait().ok()?.is_none() {
+        if start.elapsed() > HELP_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // Some programs print their usage to stderr
+    let (stdout, stderr) = (stdout.join().ok()?, stderr.join().ok()?);
+    Some(if stdout.trim().is_empty() { stderr } else { stdout })
+}
+
+fn cache_path(binary: &Path) -> Option<PathBuf> {
+    let name = path_string(binary).replace('/', "%");
+    cache_dir().map(|dir| dir.join("options").join(name))
+}
+
+/// Specs found with and without `--help` are kept apart, so allowing it
+/// later isn't hidden by a man-page-only spec
+fn cache_key(binary: &Path, run_help: bool) -> String {
+    let mtime = fs::metadata(binary).and_then(|meta| meta.modified()).ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map(|mtime| mtime.as_secs())
+        .unwrap_or(0);
+    format!("{}@{}{}", path_string(binary), mtime, if run_help { "+help" } else { "" })
+}
+
+/// Cached specs store one option per line as `<kind>\t<name>\t<description>`
+fn read_cache(binary: &Path, run_help: bool) -> Option<Spec> {
+    let contents = read_file(cache_path(binary)?);
+    let mut lines = contents.lines();
+    if lines.next() != Some(&*cache_key(binary, run_help)) {
+        return None
+    }
+    let mut spec = Spec::default();
+    for line in lines {
+        let fields: Vec<_> = line.splitn(3, '\t').collect();
+        let option = (fields.get(1)?.to_string(), fields.get(2).unwrap_or(&"").to_string());
+        match fields[0] {
+            "flag" => spec.flags.push(option),
+            _      => spec.subcommands.push(option),
+        }
+    }
+    Some(spec)
+}
+
+fn write_cache(binary: &Path, run_help: bool, spec: &Spec) {
+    let path = match cache_path(binary) {
+        Some(path) => path,
+        None => return,
+    };
+    let _ = path.parent().map(fs::create_dir_all);
+    if let Ok(mut file) = File::create(path) {
+        let _ = writeln!(file, "{}", cache_key(binary, run_help));
+        let flags = spec.flags.iter().map(|flag| ("flag", flag));
+        let subcommands = spec.subcommands.iter().map(|subcommand| ("subcommand", subcommand));
+        for (kind, (name, description)) in flags.chain(subcommands) {
+            let _ = writeln!(file, "{}\t{}\t{}", kind, name, description);
+        }
+    }
+}
+
+fn load_spec(command: &str, run_help: bool) -> Spec {
+    let binary = match which(command) {
+        Some(binary) => binary,
+        None => return Spec::default(),
+    };
+    if let Some(spec) = read_cache(&binary, run_help) {
+        return spec
+    }
+
+    let name = binary.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let mut spec = read_man_page(&name).map(|roff| parse_man(&roff)).unwrap_or_default();
+    let mut settled = true;
+    if spec.flags.is_empty() && run_help {
+        // A help run that failed or timed out may well work next time
+        match read_help(&binary) {
+            Some(help) => {
+                let help = parse_help(&help);
+                spec.flags = help.flags;
+                if spec.subcommands.is_empty() {
+                    spec.subcommands = help.subcommands;
+                }
+            }
+            None => settled = false,
+        }
+    }
+    if settled && (!spec.flags.is_empty() || !spec.subcommands.is_empty()) {
+        write_cache(&binary, run_help, &spec);
+    }
+    spec
+}
+
+impl OptionCompleter {
+    pub fn new<S: Into<String>>(command: S) -> Self {
+        OptionCompleter { command: command.into(), run_help: false, spec: None }
+    }
+
+    /// Allows running `<command> --help` when the man page lists no flags
+    pub fn run_help(&mut self, run_help: bool) -> &mut Self {
+        self.run_help = run_help;
+        self
+    }
+
+    pub fn spec(&mut self) -> &Spec {
+        if self.spec.is_none() {
+            self.spec = Some(load_spec(&self.command, self.run_help));
+        }
+        self.spec.as_ref().unwrap()
+    }
+}
+
+impl Completer for OptionCompleter {
+    fn label(&self) -> String {
+        format!("{} options", self.command)
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        let options = if query.starts_with('-') { &self.spec().flags } else { &self.spec().subcommands };
+        let candidates: Vec<_> = options.iter()
+            .map(|(name, description)| {
+                if description.is_empty() { name.clone() } else { describe(name, description) }
+            })
+            .collect();
+        let mut completions: Vec<_> = candidates.iter()
+            .filter_map(|candidate| F::matched_candidate(query, candidate))
+            .collect();
+
+        completions.sort_by(WeightedMatch::cmp);
+        completions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cache_path, flag_names, load_spec, parse_help, parse_man, strip_roff};
+    use ::util::{fixture, path_string};
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn option(name: &str, description: &str) -> (String, String) {
+        (name.to_string(), description.to_string())
+    }
+
+    #[test]
+    fn test_flag_names() {
+        assert_eq!(flag_names("-o, --output=<FILE>"), vec!["-o", "--output"]);
+        assert_eq!(flag_names("--color[=WHEN]"), vec!["--color"]);
+        assert_eq!(flag_names("-h | --help"), vec!["-h", "--help"]);
+    }
+
+    #[test]
+    fn test_parse_help() {
+        let help = "Usage: tool [OPTIONS] <COMMAND>\n\nCommands:\n  build  Compile the project\n  \
+                    run    Run it\n\nOptions:\n  -v, --verbose    Print more\n  --long-option\n          \
+                    Described below\n";
+        let spec = parse_help(help);
+        assert_eq!(spec.flags, vec![
+            option("-v", "Print more"),
+            option("--verbose", "Print more"),
+            option("--long-option", "Described below"),
+        ]);
+        assert_eq!(spec.subcommands, vec![option("build", "Compile the project"), option("run", "Run it")]);
+    }
+
+    #[test]
+    fn test_strip_roff() {
+        assert_eq!(strip_roff("\\fB\\-a\\fR, \\fB\\-\\-all\\fR"), "-a, --all");
+    }
+
+    #[test]
+    fn test_parse_man() {
+        let roff = ".SH OPTIONS\n.TP\n\\fB\\-a\\fR, \\fB\\-\\-all\\fR\ndo not ignore entries\n\
+                    .IP \"\\fB\\-l\\fR\" 4\nuse a long listing format\n";
+        assert_eq!(parse_man(roff).flags, vec![
+            option("-a", "do not ignore entries"),
+            option("--all", "do not ignore entries"),
+            option("-l", "use a long listing format"),
+        ]);
+    }
+
+    #[test]
+    fn test_load_spec_runs_help_only_when_allowed() {
+        let dir = fixture("options");
+        env::set_var("XDG_CACHE_HOME", dir.join("cache"));
+        let script = |name: &str, body: &str| {
+            let path = dir.join(name);
+            fs::write(&path, format!("#!/bin/sh\n{}", body)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+
+        let helpful = script("complesh-helpful", "echo '  -v, --verbose  Print more'\n");
+        let command = path_string(&helpful);
+        assert!(load_spec(&command, false).flags.is_empty());
+        assert!(!cache_path(&helpful).unwrap().exists());
+        assert_eq!(load_spec(&command, true).flags, vec![option("-v", "Print more"), option("--verbose", "Print more")]);
+        assert!(cache_path(&helpful).unwrap().exists());
+
+        let silent = script("complesh-silent", "");
+        assert_eq!(load_spec(&path_string(&silent), true).flags, vec![]);
+        assert!(!cache_path(&silent).unwrap().exists());
+    }
+}
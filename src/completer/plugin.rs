@@ -0,0 +1,269 @@
+use ::completer::{Completer, ListCompleter};
+use ::errors::Result;
+use ::filter::{Filter, WeightedMatch};
+use ::readkeys::Printable;
+use ::util::{describe, path_string};
+use serde_json::{self, Value};
+use std::collections::HashMap;
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// What a plugin is told about the word being completed
+pub struct Context {
+    pub query: String,
+    pub command: Option<String>,
+    pub previous: Option<String>,
+}
+
+/// Completes candidates produced by an external program.
+///
+/// complesh writes a single JSON object describing the completion to the
+/// plugin's stdin and closes it:
+///
+/// ```text
+/// {"query": "api", "cwd": "/src/app", "command": "deploy", "previous": "--to"}
+/// ```
+///
+/// The plugin answers with one JSON object per line on stdout, which are
+/// shown as they arrive. Only `value` is required; `display` is shown in
+/// its place and `description` in the dimmed second column:
+///
+/// ```text
+/// {"value": "api-prod", "display": "api (production)", "description": "us-east-1"}
+/// ```
+///
+/// The plugin is run again with the new query whenever it changes.
+pub struct PluginCompleter {
+    name: String,
+    executable: String,
+    context: Context,
+    list: ListCompleter,
+    values: Arc<Mutex<HashMap<String, String>>>,
+    child: Child,
+}
+
+/// A line of a plugin's response
+#[derive(Debug, PartialEq)]
+struct Candidate {
+    display: String,
+    description: Option<String>,
+    value: String,
+}
+
+fn request(context: &Context) -> String {
+    let cwd = env::current_dir().map(path_string).unwrap_or_default();
+    let request = json!({
+        "query": context.query,
+        "cwd": cwd,
+        "command": context.command,
+        "previous": context.previous,
+    });
+    request.to_string()
+}
+
+/// Reads a response line. Bare JSON strings are accepted as values.
+fn parse_candidate(line: &str) -> Option<Candidate> {
+    let candidate: Value = serde_json::from_str(line).ok()?;
+    if let Some(value) = candidate.as_str() {
+        return Some(Candidate { display: value.to_string(), description: None, value: value.to_string() })
+    }
+    let value = candidate.get("value")?.as_str()?;
+    Some(Candidate {
+        display: candidate.get("display").and_then(Value::as_str).unwrap_or(value).to_string(),
+        description: candidate.get("description").and_then(Value::as_str).map(str::to_string),
+        value: value.to_string(),
+    })
+}
+
+/// Picks what to show for a candidate so each display resolves to one
+/// value, adding the value to displays already taken by another. Returns
+/// `None` for repeats.
+fn unique_display(values: &HashMap<String, String>, candidate: &Candidate) -> Option<String> {
+    let display = candidate.display.value();
+    let display = match values.get(&display) {
+        Some(value) if *value != candidate.value => format!("{} ({})", display, candidate.value),
+        _ => display,
+    };
+    if values.contains_key(&display) { None } else { Some(display) }
+}
+
+/// Runs the plugin for `context`, streaming its candidates into a list
+/// and recording the value behind each display in `values`
+fn query(executable: &str, context: &Context, values: Arc<Mutex<HashMap<String, String>>>) -> Result<(Child, ListCompleter)> {
+    let mut child = Command::new(executable)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null())
+        .spawn()?;
+
+    // Dropping stdin closes it, telling the plugin the request is complete
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{}", request(context))?;
+    }
+
+    let stdout = child.stdout.take().ok_or("plugin stdout unavailable")?;
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let candidate = match parse_candidate(&line) {
+                Some(candidate) => candidate,
+                None => continue,
+            };
+            let shown = {
+                let mut values = values.lock().unwrap();
+                let display = match unique_display(&values, &candidate) {
+                    Some(display) => display,
+                    None => continue,
+                };
+                values.insert(display.clone(), candidate.value);
+                match candidate.description {
+                    Some(description) => describe(display, description),
+                    None => display,
+                }
+            };
+            if tx.send(shown).is_err() {
+                break
+            }
+        }
+    });
+    Ok((child, ListCompleter::from_receiver(rx)))
+}
+
+impl PluginCompleter {
+    pub fn spawn(name: &str, executable: &str, context: Context) -> Result<PluginCompleter> {
+        let values = Arc::new(Mutex::new(HashMap::new()));
+        let (child, list) = query(executable, &context, values.clone())?;
+        Ok(PluginCompleter { name: name.to_string(), executable: executable.to_string(), context, list, values, child })
+    }
+
+    /// Stops the running plugin and asks again for the current query. The
+    /// old answers stay up if it can't be started.
+    fn requery(&mut self) {
+        let values = Arc::new(Mutex::new(HashMap::new()));
+        if let Ok((child, list)) = query(&self.executable, &self.context, values.clone()) {
+            self.stop();
+            self.child = child;
+            self.list = list;
+            self.values = values;
+        }
+    }
+
+    fn stop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for PluginCompleter {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Completer for PluginCompleter {
+    fn label(&self) -> String {
+        if self.streaming() { format!("{}…", self.name) } else { self.name.clone() }
+    }
+
+    fn streaming(&self) -> bool {
+        self.list.streaming()
+    }
+
+    fn poll(&mut self) -> bool {
+        self.list.poll()
+    }
+
+    fn resolve(&self, selected: &str) -> String {
+        self.values.lock().unwrap().get(selected).cloned().unwrap_or(selected.to_string())
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        if query != self.context.query {
+            self.context.query = query.to_string();
+            self.requery();
+        }
+        self.list.matches::<F>(query)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Candidate, Context, PluginCompleter, parse_candidate, unique_display};
+    use ::completer::Completer;
+    use ::filter::SpacedFilter;
+    use ::readkeys::Printable;
+    use ::util::{fixture, path_string};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn candidate(display: &str, description: Option<&str>, value: &str) -> Candidate {
+        Candidate { display: display.to_string(), description: description.map(str::to_string), value: value.to_string() }
+    }
+
+    #[test]
+    fn test_parse_candidate() {
+        let full = r#"{"value": "api-prod", "display": "api (production)", "description": "us-east-1"}"#;
+        assert_eq!(parse_candidate(full), Some(candidate("api (production)", Some("us-east-1"), "api-prod")));
+        assert_eq!(parse_candidate(r#"{"value": "x"}"#), Some(candidate("x", None, "x")));
+        assert_eq!(parse_candidate(r#""y""#), Some(candidate("y", None, "y")));
+        assert_eq!(parse_candidate(r#"{"display": "z"}"#), None);
+        assert_eq!(parse_candidate("not json"), None);
+    }
+
+    #[test]
+    fn test_unique_display() {
+        let mut values = HashMap::new();
+        values.insert("api".to_string(), "api-prod".to_string());
+        assert_eq!(unique_display(&values, &candidate("web", None, "web")), Some("web".to_string()));
+        assert_eq!(unique_display(&values, &candidate("api", None, "api-dev")), Some("api (api-dev)".to_string()));
+        assert_eq!(unique_display(&values, &candidate("api", None, "api-prod")), None);
+    }
+
+    fn finish(completer: &mut PluginCompleter) {
+        let start = Instant::now();
+        while completer.streaming() && start.elapsed() < Duration::from_secs(5) {
+            completer.poll();
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn complete(completer: &mut PluginCompleter, query: &str) -> Vec<String> {
+        completer.complete::<SpacedFilter>(query);
+        finish(completer);
+        let mut completions: Vec<_> = completer.complete::<SpacedFilter>(query).iter()
+            .map(|completion| completion.without_escape_codes())
+            .collect();
+        completions.sort();
+        completions
+    }
+
+    #[test]
+    fn test_requery_as_the_query_changes() {
+        let plugin = fixture("plugin").join("plugin");
+        let script = r#"#!/bin/sh
+query=$(sed 's/.*"query":"\([^"]*\)".*/\1/')
+echo "{\"value\": \"$query-1\", \"display\": \"$query\"}"
+echo "{\"value\": \"$query-2\", \"display\": \"$query\"}"
+"#;
+        fs::write(&plugin, script).unwrap();
+        fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let context = Context { query: "api".to_string(), command: None, previous: None };
+        let mut completer = PluginCompleter::spawn("plugin", &path_string(&plugin), context).unwrap();
+        assert_eq!(complete(&mut completer, "api"), vec!["api", "api (api-2)"]);
+        assert_eq!(completer.resolve("api"), "api-1");
+        assert_eq!(completer.resolve("api (api-2)"), "api-2");
+
+        assert_eq!(complete(&mut completer, "web"), vec!["web", "web (web-2)"]);
+        assert_eq!(completer.resolve("web"), "web-1");
+    }
+}
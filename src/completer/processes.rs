@@ -0,0 +1,162 @@
+use ::accounts::user_names;
+use ::completer::Completer;
+use ::filter::{Filter, WeightedMatch};
+use ::util::{describe, read_file};
+use std::cmp::Reverse;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::process;
+
+/// Signal names and numbers as Linux defines them
+const SIGNALS: &[(&str, u32)] = &[
+    ("HUP", 1), ("INT", 2), ("QUIT", 3), ("ILL", 4), ("TRAP", 5), ("ABRT", 6),
+    ("BUS", 7), ("FPE", 8), ("KILL", 9), ("USR1", 10), ("SEGV", 11), ("USR2", 12),
+    ("PIPE", 13), ("ALRM", 14), ("TERM", 15), ("STKFLT", 16), ("CHLD", 17), ("CONT", 18),
+    ("STOP", 19), ("TSTP", 20), ("TTIN", 21), ("TTOU", 22), ("URG", 23), ("XCPU", 24),
+    ("XFSZ", 25), ("VTALRM", 26), ("PROF", 27), ("WINCH", 28), ("IO", 29), ("PWR", 30),
+    ("SYS", 31),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Process {
+    pub pid: u32,
+    pub uid: u32,
+    pub command: String,
+}
+
+/// Completes running processes from `/proc`, matching on the command line
+/// and inserting the pid. Queries starting with `-` complete signal names
+/// for `kill`.
+#[derive(Default)]
+pub struct ProcessCompleter {
+    pub only_current_user: bool,
+}
+
+/// The command name from `/proc/<pid>/stat`, which is wrapped in
+/// parentheses and may itself contain spaces and parentheses
+fn parse_comm(stat: &str) -> Option<&str> {
+    let (start, end) = (stat.find('(')?, stat.rfind(')')?);
+    if start < end { Some(&stat[start + 1..end]) } else { None }
+}
+
+/// Arguments in `/proc/<pid>/cmdline` are separated by NUL bytes
+fn parse_cmdline(cmdline: &[u8]) -> String {
+    cmdline.split(|&byte| byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn read_process(pid: u32) -> Option<Process> {
+    let dir = format!("/proc/{}", pid);
+    let uid = fs::metadata(&dir).ok()?.uid();
+    let stat = read_file(format!("{}/stat", dir));
+    let cmdline = parse_cmdline(read_file(format!("{}/cmdline", dir)).as_bytes());
+
+    // Kernel threads have no command line
+    let command = if cmdline.is_empty() { format!("[{}]", parse_comm(&stat)?) } else { cmdline };
+    Some(Process { pid, uid, command })
+}
+
+pub fn processes() -> Vec<Process> {
+    let mut processes: Vec<_> = fs::read_dir("/proc").map(|entries| {
+        entries.filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str().and_then(|name| name.parse().ok()))
+            .filter_map(read_process)
+            .collect()
+    }).unwrap_or_default();
+
+    // Newest first
+    processes.sort_by_key(|process| Reverse(process.pid));
+    processes
+}
+
+fn current_uid() -> Option<u32> {
+    fs::metadata("/proc/self").ok().map(|meta| meta.uid())
+}
+
+impl ProcessCompleter {
+    pub fn only_current_user(&mut self, only_current_user: bool) -> &mut Self {
+        self.only_current_user = only_current_user;
+        self
+    }
+
+    fn candidates(&self, query: &str) -> Vec<String> {
+        if query.starts_with('-') {
+            return SIGNALS.iter()
+                .map(|&(name, number)| describe(format!("-{}", name), number))
+                .collect()
+        }
+
+        let (users, own_pid, uid) = (user_names(), process::id(), current_uid());
+        processes().into_iter()
+            .filter(|process| process.pid != own_pid)
+            .filter(|process| !self.only_current_user || Some(process.uid) == uid)
+            .map(|process| {
+                let user = users.get(&process.uid).cloned().unwrap_or(process.uid.to_string());
+                describe(process.pid, format!("{:<10} {}", user, process.command))
+            })
+            .collect()
+    }
+}
+
+impl Completer for ProcessCompleter {
+    fn label(&self) -> String {
+        if self.only_current_user { "processes [mine]" } else { "processes" }.to_string()
+    }
+
+    fn toggle_mode(&mut self) {
+        self.only_current_user = !self.only_current_user;
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        // Processes are found by their command line, which is in the
+        // description column, so the whole row is matched
+        let signals = query.starts_with('-');
+        let mut completions: Vec<_> = self.candidates(query).iter()
+            .filter_map(|candidate| if signals {
+                F::matched_candidate(query, candidate)
+            } else {
+                F::matched(query, candidate)
+            })
+            .collect();
+
+        completions.sort_by(WeightedMatch::cmp);
+        completions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ProcessCompleter, parse_comm, parse_cmdline};
+    use ::completer::Completer;
+    use ::filter::SpacedFilter;
+    use ::readkeys::Printable;
+    use std::process::Command;
+
+    #[test]
+    fn test_parse_comm() {
+        assert_eq!(parse_comm("42 (tmux: server) S 1 42"), Some("tmux: server"));
+        assert_eq!(parse_comm("42 (a) b) S 1 42"), Some("a) b"));
+        assert_eq!(parse_comm("42"), None);
+    }
+
+    #[test]
+    fn test_parse_cmdline() {
+        assert_eq!(parse_cmdline(b"vim\0src/main.rs\0"), "vim src/main.rs");
+        assert_eq!(parse_cmdline(b""), "");
+    }
+
+    #[test]
+    fn test_matches_the_command_line_and_inserts_the_pid() {
+        let mut child = Command::new("sleep").arg("73573").spawn().unwrap();
+        let pids: Vec<_> = ProcessCompleter::default().complete::<SpacedFilter>("sleep 73573").iter()
+            .map(|row| row.value())
+            .collect();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(pids, vec![child.id().to_string()]);
+    }
+}
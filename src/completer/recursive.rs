@@ -1,14 +1,17 @@
 use ::completer::{Completer, CompleterBase};
-use ::ring_buffer::RingBuffer;
-use ::filter::Filter;
-use ::util::{search_root, path_string, git_root};
+use ::filter::{Filter, WeightedMatch};
+use ::util::{absolute_path, search_root, path_string, git_root};
 use walkdir::WalkDir;
 use std::path::Path;
+use std::time::Duration;
 
 pub struct RecursiveCompleter {
     max_depth: usize,
     max_git_depth: usize,
     follow_links: bool,
+    /// How long a listing is reused, which is how long a new file can go
+    /// unnoticed by a long-lived completer
+    pub refresh: Duration,
     base: CompleterBase,
 }
 
@@ -18,6 +21,7 @@ impl Default for RecursiveCompleter {
             max_depth: 2,
             max_git_depth: 32,
             follow_links: false,
+            refresh: Duration::from_secs(5),
             base: CompleterBase::new(),
         }
     }
@@ -46,11 +50,14 @@ impl Completer for RecursiveCompleter {
         "recursive".to_string()
     }
 
-    fn complete<F: Filter>(&mut self, query: &str) -> RingBuffer<String> {
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
         let root = path_string(search_root(query));
         let (links, depth) = (self.follow_links, self.max_depth(query));
 
-        self.base.complete::<F, _>(query, &*root, || {
+        // A relative root names a different directory once the cwd changes
+        let key = format!("{}#{}", path_string(absolute_path(&root)), depth);
+        self.base.expire(self.refresh);
+        self.base.matches::<F, _>(query, &*key, || {
             WalkDir::new(&root)
                 .follow_links(links)
                 .max_depth(depth)
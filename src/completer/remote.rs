@@ -0,0 +1,156 @@
+use ::completer::{Completer, HostCompleter, MixedCompleter};
+use ::filter::{Filter, WeightedMatch};
+use ::util::{describe, DESCRIPTION_SEPARATOR};
+
+/// Completes the arguments of scp and rsync, which are either local paths
+/// or `[user@]host:path`. Hosts are offered once the word has a `@` or `:`
+/// in it, or when no local path matches; otherwise it completes paths.
+pub struct RemoteCompleter {
+    hosts: HostCompleter,
+    paths: MixedCompleter,
+    remote: bool,
+}
+
+impl Default for RemoteCompleter {
+    fn default() -> RemoteCompleter {
+        RemoteCompleter::new(HostCompleter::default(), MixedCompleter::default())
+    }
+}
+
+fn is_path(query: &str) -> bool {
+    query.starts_with('/') || query.starts_with('.') || query.starts_with('~')
+}
+
+/// Splits `user@host:path` into its user prefix, host and path suffix,
+/// keeping the `@` and `:` with the parts around the host
+fn split_remote(query: &str) -> (&str, &str, &str) {
+    let (remote, path) = query.split_at(query.find(':').unwrap_or(query.len()));
+    match remote.rfind('@') {
+        Some(idx) => (&remote[..idx + 1], &remote[idx + 1..], path),
+        None => ("", remote, path),
+    }
+}
+
+impl RemoteCompleter {
+    pub fn new(hosts: HostCompleter, paths: MixedCompleter) -> Self {
+        RemoteCompleter { hosts, paths, remote: false }
+    }
+
+    fn complete_hosts<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        let (user, host, path) = split_remote(query);
+        let suffix = if path.is_empty() { ":" } else { path };
+        let skipped = user.chars().count();
+        self.hosts.matches::<F>(host).into_iter()
+            .map(|m| {
+                let result = {
+                    let mut parts = m.result.splitn(2, DESCRIPTION_SEPARATOR);
+                    let name = format!("{}{}{}", user, parts.next().unwrap_or(""), suffix);
+                    match parts.next() {
+                        Some(description) => describe(name, description),
+                        None => name,
+                    }
+                };
+                WeightedMatch {
+                    result,
+                    positions: m.positions.iter().map(|position| position + skipped).collect(),
+                    ..m
+                }
+            })
+            .collect()
+    }
+}
+
+impl Completer for RemoteCompleter {
+    fn label(&self) -> String {
+        if self.remote {
+            self.hosts.label()
+        } else {
+            self.paths.label()
+        }
+    }
+
+    fn completes_paths(&self) -> bool {
+        !self.remote
+    }
+
+    fn toggle_mode(&mut self) {
+        self.paths.toggle_mode();
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        self.remote = !is_path(query) && query.contains(&['@', ':'][..]);
+        if !self.remote {
+            let completions = self.paths.matches::<F>(query);
+            if !completions.is_empty() || is_path(query) {
+                return completions
+            }
+            self.remote = true;
+        }
+        self.complete_hosts::<F>(query)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RemoteCompleter, split_remote};
+    use ::completer::{Completer, Host, HostCompleter, MixedCompleter, Mode};
+    use ::filter::SpacedFilter;
+    use ::readkeys::Printable;
+    use ::util::{fixture, lock_cwd, path_string};
+    use std::fs::File;
+
+    fn completer() -> RemoteCompleter {
+        let hosts = vec![
+            Host { name: "zyzzyva".to_string(), hostname: Some("zyzzyva.example.com".to_string()), user: None },
+            Host { name: "zymurgy".to_string(), ..Host::default() },
+        ];
+        let mut paths = MixedCompleter::default();
+        paths.mode(Mode::Recursive);
+        RemoteCompleter::new(HostCompleter::new(hosts), paths)
+    }
+
+    fn complete(completer: &mut RemoteCompleter, query: &str) -> Vec<String> {
+        completer.complete::<SpacedFilter>(query).iter()
+            .map(|completion| completion.without_escape_codes())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_remote() {
+        assert_eq!(split_remote("web"), ("", "web", ""));
+        assert_eq!(split_remote("root@web"), ("root@", "web", ""));
+        assert_eq!(split_remote("root@web:/var/log"), ("root@", "web", ":/var/log"));
+        assert_eq!(split_remote("web:a@b"), ("", "web", ":a@b"));
+    }
+
+    #[test]
+    fn test_prefers_local_paths() {
+        let dir = fixture("remote");
+        File::create(dir.join("zyzzyva.tar")).unwrap();
+        let mut completer = completer();
+
+        let query = format!("{}/zyz", path_string(&dir));
+        assert_eq!(complete(&mut completer, &query), vec![format!("{}/zyzzyva.tar", path_string(&dir))]);
+        assert!(completer.completes_paths());
+
+        let query = format!("{}/nothing", path_string(&dir));
+        assert!(complete(&mut completer, &query).is_empty());
+    }
+
+    #[test]
+    fn test_completes_hosts() {
+        let mut completer = completer();
+        assert_eq!(complete(&mut completer, "root@zyzz"), vec!["root@zyzzyva:\tzyzzyva.example.com"]);
+        assert_eq!(complete(&mut completer, "zymu:/tmp"), vec!["zymurgy:/tmp"]);
+        assert_eq!(completer.label(), "hosts");
+        assert!(!completer.completes_paths());
+    }
+
+    #[test]
+    fn test_falls_back_to_hosts() {
+        let _cwd = lock_cwd();
+        let mut completer = completer();
+        assert_eq!(complete(&mut completer, "zymurgy"), vec!["zymurgy:"]);
+        assert_eq!(completer.label(), "hosts");
+    }
+}
@@ -0,0 +1,298 @@
+use ::completer::Completer;
+use ::filter::{Filter, WeightedMatch};
+use ::util::{describe, find_upwards, read_file};
+use serde_json::{self, Value};
+use std::fs;
+use std::path::Path;
+
+const MAKEFILES: &[&str] = &["GNUmakefile", "makefile", "Makefile"];
+const JUSTFILES: &[&str] = &["justfile", "Justfile", ".justfile"];
+const PACKAGE_JSON: &[&str] = &["package.json"];
+const CARGO_MANIFEST: &[&str] = &["Cargo.toml"];
+
+/// A task name and the line previewed next to it
+pub type Target = (String, String);
+
+/// Completes the targets of the build file nearest the working directory:
+/// make targets, just recipes, npm scripts, or cargo binaries, examples
+/// and tests. Build files are parsed directly rather than by the tools.
+pub struct TargetCompleter {
+    tool: String,
+    previous: Option<String>,
+}
+
+/// Finds the `:` ending a rule's target list, skipping `:=` and `::=`
+/// assignments and variables whose value has a colon in it
+fn rule_colon(line: &str) -> Option<usize> {
+    let idx = line.find(':')?;
+    let (before, after) = (&line[..idx], &line[idx..]);
+    if before.contains('=') || after.starts_with(":=") || after.starts_with("::=") {
+        None
+    } else {
+        Some(idx)
+    }
+}
+
+/// Finds the `:` ending a recipe's name and parameters, skipping `:=`
+/// assignments and colons in quoted or parenthesized default values
+fn recipe_colon(line: &str) -> Option<usize> {
+    let (mut quote, mut depth) = (None, 0);
+    for (idx, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"') | (None, '\'') | (None, '`') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ':') if depth == 0 => {
+                return if line[idx..].starts_with(":=") { None } else { Some(idx) }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+fn parse_makefile(contents: &str) -> Vec<Target> {
+    let lines: Vec<_> = contents.lines().collect();
+    let mut targets: Vec<Target> = vec![];
+    for (idx, line) in lines.iter().enumerate() {
+        if line.starts_with(|c: char| c.is_whitespace() || c == '#') {
+            continue
+        }
+        let colon = match rule_colon(line) {
+            Some(colon) => colon,
+            None => continue,
+        };
+
+        // Prefer a `target: ## help` comment, falling back to the recipe
+        let help = line.find("##").map(|start| line[start + 2..].trim().to_string());
+        let recipe = lines.get(idx + 1)
+            .filter(|next| next.starts_with('\t'))
+            .map(|next| next.trim().to_string());
+        let preview = help.or(recipe).unwrap_or_default();
+
+        for name in line[..colon].split_whitespace() {
+            let special = name.starts_with('.') || name.contains('%') || name.contains('$');
+            if !special && !targets.iter().any(|target| target.0 == name) {
+                targets.push((name.to_string(), preview.clone()));
+            }
+        }
+    }
+    targets
+}
+
+fn parse_justfile(contents: &str) -> Vec<Target> {
+    const KEYWORDS: &[&str] = &["alias", "export", "import", "mod", "set"];
+    let lines: Vec<_> = contents.lines().collect();
+    let mut recipes = vec![];
+    for (idx, line) in lines.iter().enumerate() {
+        if line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '[') || line.is_empty() {
+            continue
+        }
+        let colon = match recipe_colon(line) {
+            Some(colon) => colon,
+            None => continue,
+        };
+        let name = line[..colon].split_whitespace().next().unwrap_or("").trim_start_matches('@');
+        if name.is_empty() || name.starts_with('_') || KEYWORDS.contains(&name) {
+            continue
+        }
+
+        // A comment right above a recipe is its documentation
+        let doc = idx.checked_sub(1)
+            .and_then(|previous| lines.get(previous))
+            .filter(|previous| previous.starts_with('#'))
+            .map(|previous| previous.trim_start_matches('#').trim().to_string());
+        let body = lines.get(idx + 1)
+            .filter(|next| next.starts_with(|c: char| c.is_whitespace()))
+            .map(|next| next.trim().to_string());
+        recipes.push((name.to_string(), doc.or(body).unwrap_or_default()));
+    }
+    recipes
+}
+
+fn parse_package_json(contents: &str) -> Vec<Target> {
+    let package: Value = match serde_json::from_str(contents) {
+        Ok(package) => package,
+        Err(_) => return vec![],
+    };
+    match package.get("scripts").and_then(Value::as_object) {
+        Some(scripts) => scripts.iter()
+            .map(|(name, script)| (name.clone(), script.as_str().unwrap_or("").to_string()))
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Collects `name = "..."` from `[package]` and the `[[bin]]`, `[[example]]`
+/// and `[[test]]` tables of a Cargo manifest
+fn parse_cargo_manifest(contents: &str) -> Vec<Target> {
+    let mut section = String::new();
+    let mut targets = vec![];
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            section = line.trim_matches(|c| c == '[' || c == ']').trim().to_string();
+            continue
+        }
+        let mut assignment = line.splitn(2, '=');
+        if assignment.next().map(str::trim) != Some("name") {
+            continue
+        }
+        let name = assignment.next().unwrap_or("").trim().trim_matches('"').to_string();
+        let kind = match &*section {
+            "package" => "package",
+            "bin"     => "bin",
+            "example" => "example",
+            "test"    => "test",
+            _         => continue,
+        };
+        targets.push((name, kind.to_string()));
+    }
+    targets
+}
+
+/// Names of the targets cargo discovers from the directory layout
+fn discover_cargo_targets(root: &Path, dir: &str, kind: &str) -> Vec<Target> {
+    let entries = match fs::read_dir(root.join(dir)) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut targets: Vec<_> = entries.filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rs") || path.join("main.rs").is_file())
+        .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+        .map(|name| (name, kind.to_string()))
+        .collect();
+    targets.sort();
+    targets
+}
+
+fn cargo_targets(manifest: &Path) -> Vec<Target> {
+    let root = manifest.parent().unwrap_or(Path::new("."));
+    let has_main = root.join("src").join("main.rs").is_file();
+
+    // The package name only names a binary when there's a src/main.rs
+    let mut declared: Vec<_> = parse_cargo_manifest(&read_file(manifest)).into_iter()
+        .filter_map(|(name, kind)| match &*kind {
+            "package" if has_main => Some((name, "bin".to_string())),
+            "package"             => None,
+            _                     => Some((name, kind)),
+        })
+        .collect();
+
+    let discovered = discover_cargo_targets(root, "src/bin", "bin").into_iter()
+        .chain(discover_cargo_targets(root, "examples", "example"))
+        .chain(discover_cargo_targets(root, "tests", "test"));
+    for target in discovered {
+        if !declared.contains(&target) {
+            declared.push(target);
+        }
+    }
+    declared
+}
+
+impl TargetCompleter {
+    pub fn new<S: Into<String>>(tool: S, previous: Option<String>) -> Self {
+        TargetCompleter { tool: tool.into(), previous }
+    }
+
+    fn targets(&self) -> Vec<Target> {
+        let find = |names: &[&str]| find_upwards(".", names);
+        match &*self.tool {
+            "make" => find(MAKEFILES).map(|path| parse_makefile(&read_file(path))),
+            "just" => find(JUSTFILES).map(|path| parse_justfile(&read_file(path))),
+            "npm" | "yarn" | "pnpm" => find(PACKAGE_JSON).map(|path| parse_package_json(&read_file(path))),
+            "cargo" => find(CARGO_MANIFEST).map(|path| {
+                let targets = cargo_targets(&path);
+                match self.previous.as_ref().map(|previous| previous.trim_start_matches('-')) {
+                    Some(kind) if kind == "bin" || kind == "example" || kind == "test" => {
+                        targets.into_iter().filter(|target| target.1 == kind).collect()
+                    }
+                    _ => targets,
+                }
+            }),
+            _ => None,
+        }.unwrap_or_default()
+    }
+}
+
+impl Completer for TargetCompleter {
+    fn label(&self) -> String {
+        format!("{} targets", self.tool)
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        let candidates: Vec<_> = self.targets().into_iter()
+            .map(|(name, preview)| if preview.is_empty() { name } else { describe(name, preview) })
+            .collect();
+        let mut completions: Vec<_> = candidates.iter()
+            .filter_map(|candidate| F::matched_candidate(query, candidate))
+            .collect();
+
+        completions.sort_by(WeightedMatch::cmp);
+        completions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_makefile, parse_justfile, parse_package_json, parse_cargo_manifest};
+
+    fn target(name: &str, preview: &str) -> (String, String) {
+        (name.to_string(), preview.to_string())
+    }
+
+    #[test]
+    fn test_parse_makefile() {
+        let makefile = "CC := gcc\nFLAGS = -O2\n.PHONY: all\nall: build test\n\n\
+                        build: main.c ## Compile everything\n\t$(CC) main.c\n\
+                        test clean:\n\trm -f a.out\n%.o: %.c\n\t$(CC) -c $<\n";
+        assert_eq!(parse_makefile(makefile), vec![
+            target("all", ""),
+            target("build", "Compile everything"),
+            target("test", "rm -f a.out"),
+            target("clean", "rm -f a.out"),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_justfile() {
+        let justfile = "set shell := [\"bash\", \"-c\"]\nversion := \"1\"\n\n\
+                        # Run the test suite\ntest *ARGS:\n    cargo test {{ARGS}}\n\n\
+                        @build:\n    cargo build\n_private:\n    true\n";
+        assert_eq!(parse_justfile(justfile), vec![
+            target("test", "Run the test suite"),
+            target("build", "cargo build"),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_justfile_parameters() {
+        let justfile = "url := \"http://localhost\"\n\
+                        # Serve the site\nserve port=\"8080\" host=\"127.0.0.1:80\": build\n    python -m http.server {{port}}\n\
+                        deploy target=(\"a\" + \":b\"):\n    ./deploy {{target}}\n";
+        assert_eq!(parse_justfile(justfile), vec![
+            target("serve", "Serve the site"),
+            target("deploy", "./deploy {{target}}"),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_package_json() {
+        let package = r#"{"name": "app", "scripts": {"build": "tsc", "test": "jest"}}"#;
+        assert_eq!(parse_package_json(package), vec![target("build", "tsc"), target("test", "jest")]);
+        assert!(parse_package_json("{").is_empty());
+    }
+
+    #[test]
+    fn test_parse_cargo_manifest() {
+        let manifest = "[package]\nname = \"complesh\"\n\n[dependencies]\nname = \"x\"\n\n\
+                        [[bin]]\nname = \"tool\"\n\n[[example]]\nname = \"demo\"\n";
+        assert_eq!(parse_cargo_manifest(manifest), vec![
+            target("complesh", "package"),
+            target("tool", "bin"),
+            target("demo", "example"),
+        ]);
+    }
+}
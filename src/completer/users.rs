@@ -0,0 +1,137 @@
+use ::accounts::{Group, User, users, groups};
+use ::completer::Completer;
+use ::filter::{Filter, WeightedMatch};
+use ::util::describe;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Accounts {
+    /// User names, or `user:group` once the query has a colon, as `chown` takes
+    Users,
+    Groups,
+    /// `~user/` home directories
+    Homes,
+}
+
+/// Completes local user and group names
+pub struct UserCompleter {
+    pub accounts: Accounts,
+    users: Vec<User>,
+    groups: Vec<Group>,
+}
+
+impl Default for UserCompleter {
+    fn default() -> UserCompleter {
+        UserCompleter::new(Accounts::Users)
+    }
+}
+
+/// Whether the query is a `~user` still being typed
+pub fn is_home(query: &str) -> bool {
+    query.starts_with('~') && query.len() > 1 && !query.contains('/')
+}
+
+fn user_candidates(users: &[User]) -> Vec<String> {
+    users.iter()
+        .map(|user| match user.gecos.split(',').next() {
+            Some(name) if !name.is_empty() => describe(&user.name, name),
+            _ => user.name.clone(),
+        })
+        .collect()
+}
+
+fn group_candidates(groups: &[Group], prefix: &str) -> Vec<String> {
+    groups.iter()
+        .map(|group| describe(format!("{}{}", prefix, group.name), group.gid))
+        .collect()
+}
+
+fn home_candidates(users: &[User]) -> Vec<String> {
+    users.iter()
+        .map(|user| describe(format!("~{}/", user.name), &user.home))
+        .collect()
+}
+
+impl UserCompleter {
+    /// Completes the accounts in `/etc/passwd` and `/etc/group`
+    pub fn new(accounts: Accounts) -> Self {
+        UserCompleter::with_accounts(accounts, users().to_vec(), groups().to_vec())
+    }
+
+    pub fn with_accounts(accounts: Accounts, users: Vec<User>, groups: Vec<Group>) -> Self {
+        UserCompleter { accounts, users, groups }
+    }
+
+    fn candidates(&self, query: &str) -> Vec<String> {
+        match (self.accounts, query.find(':')) {
+            (Accounts::Users, Some(idx)) => group_candidates(&self.groups, &query[..idx + 1]),
+            (Accounts::Users, None)      => user_candidates(&self.users),
+            (Accounts::Groups, _)        => group_candidates(&self.groups, ""),
+            (Accounts::Homes, _)         => home_candidates(&self.users),
+        }
+    }
+}
+
+impl Completer for UserCompleter {
+    fn label(&self) -> String {
+        match self.accounts {
+            Accounts::Users  => "users",
+            Accounts::Groups => "groups",
+            Accounts::Homes  => "homes",
+        }.to_string()
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        let candidates = self.candidates(query);
+        let mut completions: Vec<_> = candidates.iter()
+            .filter_map(|candidate| F::matched_candidate(query, candidate))
+            .collect();
+
+        completions.sort_by(WeightedMatch::cmp);
+        completions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Accounts, UserCompleter};
+    use ::accounts::{parse_group, parse_passwd};
+    use ::completer::Completer;
+    use ::filter::SpacedFilter;
+    use ::readkeys::Printable;
+    use ::util::{fixture, read_file};
+    use std::fs;
+
+    fn completer(accounts: Accounts) -> UserCompleter {
+        let dir = fixture("users");
+        fs::write(dir.join("passwd"), "root:x:0:0:root:/root:/bin/sh\n\
+                                       alice:x:1000:1000:Alice Liddell,,,:/home/alice:/bin/zsh\n").unwrap();
+        fs::write(dir.join("group"), "wheel:x:10:alice\nstaff:x:50:\n").unwrap();
+        let users = parse_passwd(&read_file(dir.join("passwd")));
+        let groups = parse_group(&read_file(dir.join("group")));
+        UserCompleter::with_accounts(accounts, users, groups)
+    }
+
+    fn complete(completer: &mut UserCompleter, query: &str) -> Vec<String> {
+        completer.complete::<SpacedFilter>(query).iter()
+            .map(|completion| completion.without_escape_codes())
+            .collect()
+    }
+
+    #[test]
+    fn test_users_then_groups_after_a_colon() {
+        let mut users = completer(Accounts::Users);
+        assert_eq!(complete(&mut users, "ali"), vec!["alice\tAlice Liddell"]);
+        assert_eq!(complete(&mut users, "alice:wh"), vec!["alice:wheel\t10"]);
+        assert!(complete(&mut users, "alice:").iter().all(|group| group.starts_with("alice:")));
+
+        let mut groups = completer(Accounts::Groups);
+        assert_eq!(complete(&mut groups, "sta"), vec!["staff\t50"]);
+    }
+
+    #[test]
+    fn test_homes() {
+        let mut homes = completer(Accounts::Homes);
+        assert_eq!(complete(&mut homes, "~ali"), vec!["~alice/\t/home/alice"]);
+        assert_eq!(complete(&mut homes, "~ro"), vec!["~root/\t/root"]);
+    }
+}
@@ -0,0 +1,105 @@
+use ::completer::Completer;
+use ::filter::{Filter, WeightedMatch};
+use ::util::describe;
+use std::collections::BTreeMap;
+use std::env;
+
+/// Longest value shown in the description column
+const MAX_VALUE_WIDTH: usize = 60;
+
+/// Completes `$NAME` and `${NAME}` from the environment and from shell
+/// variables handed over by the shell integration
+#[derive(Default)]
+pub struct VariableCompleter {
+    shell: Vec<String>,
+}
+
+/// Whether the query is a variable name still being typed
+pub fn is_variable(query: &str) -> bool {
+    let name = match query.strip_prefix("${").or_else(|| query.strip_prefix('$')) {
+        Some(name) => name,
+        None => return false,
+    };
+    name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn preview(value: &str) -> String {
+    let value: String = value.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    if value.chars().count() > MAX_VALUE_WIDTH {
+        format!("{}…", value.chars().take(MAX_VALUE_WIDTH - 1).collect::<String>())
+    } else {
+        value
+    }
+}
+
+impl VariableCompleter {
+    pub fn shell_variables(&mut self, names: Vec<String>) -> &mut Self {
+        self.shell.extend(names);
+        self
+    }
+
+    fn variables(&self) -> BTreeMap<String, String> {
+        let mut variables: BTreeMap<_, _> = self.shell.iter()
+            .map(|name| (name.clone(), String::new()))
+            .collect();
+        // A name that isn't UTF-8 couldn't be typed as `$NAME` anyway
+        variables.extend(env::vars_os().filter_map(|(name, value)| {
+            name.into_string().ok().map(|name| (name, value.to_string_lossy().to_string()))
+        }));
+        variables
+    }
+}
+
+impl Completer for VariableCompleter {
+    fn label(&self) -> String {
+        "variables".to_string()
+    }
+
+    fn matches<F: Filter>(&mut self, query: &str) -> Vec<WeightedMatch> {
+        let braced = query.starts_with("${");
+        let candidates: Vec<_> = self.variables().into_iter()
+            .map(|(name, value)| {
+                let name = if braced { format!("${{{}}}", name) } else { format!("${}", name) };
+                describe(name, preview(&value))
+            })
+            .collect();
+
+        let mut completions: Vec<_> = candidates.iter()
+            .filter_map(|candidate| F::matched_candidate(query, candidate))
+            .collect();
+
+        completions.sort_by(WeightedMatch::cmp);
+        completions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{VariableCompleter, is_variable};
+    use ::completer::Completer;
+    use ::filter::SpacedFilter;
+    use ::readkeys::Printable;
+    use std::env;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn test_is_variable() {
+        assert!(is_variable("$"));
+        assert!(is_variable("$HO"));
+        assert!(is_variable("${HO"));
+        assert!(!is_variable("$HOME/src"));
+        assert!(!is_variable("src"));
+    }
+
+    #[test]
+    fn test_values_that_are_not_utf8() {
+        env::set_var("COMPLESH_TEST_LATIN1", OsStr::from_bytes(b"caf\xe9"));
+        let completions = VariableCompleter::default().complete::<SpacedFilter>("$COMPLESH_TEST_LATIN1");
+        let candidate = completions.iter().next().unwrap();
+        assert_eq!(candidate.value(), "$COMPLESH_TEST_LATIN1");
+        assert_eq!(candidate.description().map(|description| description.without_escape_codes()), Some("caf\u{fffd}".to_string()));
+    }
+}
@@ -0,0 +1,172 @@
+use ::util::config_dir;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Completers picked for a command when the config doesn't name one. A
+/// key of `<command> <word>` only applies right after that word, and
+/// `<command> 1` only to the first argument.
+const DEFAULT_COMMANDS: &[(&str, &str)] = &[
+    ("ssh", "hosts"),
+    ("scp", "remote"),
+    ("sftp", "hosts"),
+    ("rsync", "remote"),
+    ("kill", "processes"),
+    ("strace -p", "processes"),
+    ("gdb -p", "processes"),
+    ("gdb --pid", "processes"),
+    ("chown 1", "users"),
+    ("chgrp 1", "groups"),
+    ("su 1", "users"),
+    ("sudo -u", "users"),
+    ("sudo -g", "groups"),
+    ("make", "targets"),
+    ("just", "targets"),
+    ("npm run", "targets"),
+    ("yarn run", "targets"),
+    ("pnpm run", "targets"),
+    ("cargo --bin", "targets"),
+    ("cargo --example", "targets"),
+    ("cargo --test", "targets"),
+];
+
+/// Settings read from `$XDG_CONFIG_HOME/complesh/config`, an INI-style
+/// file of `key = value` lines grouped under `[section]` headers:
+///
+/// ```text
+/// [commands]
+/// ssh = hosts
+///
+/// [plugins]
+/// deploy = /usr/local/bin/complete-deploy-targets
+///
+/// [help]
+/// mytool = yes
+/// ```
+#[derive(Debug, Default)]
+pub struct Config {
+    entries: Vec<(String, String, String)>,
+}
+
+impl Config {
+    pub fn load() -> Config {
+        config_dir()
+            .map(|dir| Config::load_path(dir.join("config")))
+            .unwrap_or_default()
+    }
+
+    pub fn load_path<P: AsRef<Path>>(path: P) -> Config {
+        let mut contents = String::new();
+        match File::open(path).and_then(|mut file| file.read_to_string(&mut contents)) {
+            Ok(_) => Config::parse(&contents),
+            Err(_) => Config::default(),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Config {
+        let mut section = String::new();
+        let mut entries = vec![];
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_string();
+            } else if let Some(idx) = line.find('=') {
+                let key = line[..idx].trim().to_string();
+                let value = line[idx + 1..].trim().to_string();
+                entries.push((section.clone(), key, value));
+            }
+        }
+        Config { entries }
+    }
+
+    /// Every `key = value` pair in a section, in file order
+    pub fn section<'a>(&'a self, section: &'a str) -> impl Iterator<Item=(&'a str, &'a str)> + 'a {
+        self.entries.iter()
+            .filter(move |entry| entry.0 == section)
+            .map(|entry| (&*entry.1, &*entry.2))
+    }
+
+    /// The last value set for a key, so later lines override earlier ones
+    pub fn get<'a>(&'a self, section: &'a str, key: &str) -> Option<&'a str> {
+        self.section(section).filter(|&(k, _)| k == key).map(|(_, value)| value).last()
+    }
+
+    /// Executable registered under `[plugins]` to complete a command
+    pub fn plugin_for(&self, command: &str) -> Option<&str> {
+        let command = Path::new(command).file_name().and_then(|name| name.to_str()).unwrap_or(command);
+        self.get("plugins", command)
+    }
+
+    /// Whether `[help]` allows running `<command> --help` to find options
+    /// the man page doesn't list
+    pub fn runs_help(&self, command: &str) -> bool {
+        let command = Path::new(command).file_name().and_then(|name| name.to_str()).unwrap_or(command);
+        match self.get("help", command) {
+            Some(value) => ["yes", "true", "on", "1"].contains(&&*value.to_lowercase()),
+            None => false,
+        }
+    }
+
+    fn mapped(&self, key: &str) -> Option<&str> {
+        self.get("commands", key).or_else(|| {
+            DEFAULT_COMMANDS.iter()
+                .find(|&&(name, _)| name == key)
+                .map(|&(_, completer)| completer)
+        })
+    }
+
+    /// Name of the completer configured under `[commands]` for a command,
+    /// preferring a mapping for the word before the cursor
+    pub fn completer_for<'a>(&'a self, command: &'a str, previous: Option<&'a str>) -> Option<&'a str> {
+        let basename = |path: &'a str| Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or(path);
+        let command = basename(command);
+        let previous = previous.map(|previous| {
+            if basename(previous) == command { "1" } else { previous }
+        });
+        previous
+            .and_then(|previous| self.mapped(&format!("{} {}", command, previous)))
+            .or_else(|| self.mapped(command))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+
+    #[test]
+    fn test_parse_sections() {
+        let config = Config::parse("# comment\ntop = 1\n[commands]\nssh = list\n\n[other]\nssh = x\n");
+        assert_eq!(config.get("", "top"), Some("1"));
+        assert_eq!(config.get("commands", "ssh"), Some("list"));
+        assert_eq!(config.get("other", "ssh"), Some("x"));
+        assert_eq!(config.get("commands", "missing"), None);
+    }
+
+    #[test]
+    fn test_completer_for_defaults() {
+        let config = Config::parse("[commands]\nscp = recursive\n");
+        assert_eq!(config.completer_for("/usr/bin/ssh", None), Some("hosts"));
+        assert_eq!(config.completer_for("rsync", None), Some("remote"));
+        assert_eq!(config.completer_for("scp", Some("-r")), Some("recursive"));
+        assert_eq!(config.completer_for("ls", None), None);
+    }
+
+    #[test]
+    fn test_runs_help() {
+        let config = Config::parse("[help]\ncargo = yes\nrustc = no\n");
+        assert!(config.runs_help("/usr/bin/cargo"));
+        assert!(!config.runs_help("rustc"));
+        assert!(!config.runs_help("ls"));
+    }
+
+    #[test]
+    fn test_completer_for_previous_word() {
+        let config = Config::parse("");
+        assert_eq!(config.completer_for("strace", Some("-p")), Some("processes"));
+        assert_eq!(config.completer_for("strace", Some("-f")), None);
+        assert_eq!(config.completer_for("chown", Some("chown")), Some("users"));
+        assert_eq!(config.completer_for("chown", Some("alice")), None);
+    }
+}
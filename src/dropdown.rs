@@ -1,17 +1,15 @@
 use termion::cursor::{Goto, Right, Down};
-use termion::raw::{IntoRawMode, RawTerminal};
-use termion::{clear, terminal_size};
-use std::io::{Write, Stdout, stdout};
+use termion::clear;
 use std::fmt::Display;
 use std::cmp::{max, min};
 use ::errors::Result;
+use ::terminal::{Terminal, Tty};
 
 const MIN_HEIGHT: u16 = 5;
 
-use ::util;
-
-pub struct Dropdown {
-    stdout: RawTerminal<Stdout>,
+/// Draws on the controlling terminal so stdout stays free for the result
+pub struct Dropdown<T: Terminal = Tty> {
+    stdout: T,
     start: Goto,
     origin: Goto,
     pub max_height: u16,
@@ -19,17 +17,24 @@ pub struct Dropdown {
 }
 
 impl Dropdown {
-    pub fn new(max_height: u16) -> Self {
-        let mut out = stdout().into_raw_mode().unwrap();
-        let (x, y) = util::sync_cursor_pos(&mut out).unwrap();
+    /// Fails when there is no controlling terminal to draw on
+    pub fn new(max_height: u16) -> Result<Self> {
+        Dropdown::with_terminal(Tty::open()?, max_height)
+    }
+}
+
+impl<T: Terminal> Dropdown<T> {
+    /// Draws on `terminal` below wherever its cursor is
+    pub fn with_terminal(mut terminal: T, max_height: u16) -> Result<Self> {
+        let (x, y) = terminal.cursor_pos()?;
         let origin = if x == 1 { Goto(1, y) } else { Goto(1, y+1) };
-        Self {
+        Ok(Self {
             start: Goto(x, y),
-            stdout: out,
+            stdout: terminal,
             height: max_height,
             max_height,
             origin
-        }
+        })
     }
 
     pub fn goto_origin(&mut self) -> Result<&mut Self> {
@@ -37,8 +42,14 @@ impl Dropdown {
         Ok(self)
     }
 
+    /// Columns a line can take up before the terminal wraps it
+    pub fn width(&self) -> Result<u16> {
+        Ok(self.stdout.size()?.0)
+    }
+
     pub fn resize(&mut self) -> Result<&mut Self> {
-        self.height = max(MIN_HEIGHT, min(util::window_height()? - self.start.1, self.max_height));
+        let rows = self.stdout.size()?.1;
+        self.height = max(MIN_HEIGHT, min(rows.saturating_sub(self.start.1), self.max_height));
         Ok(self)
     }
 
@@ -47,7 +58,8 @@ impl Dropdown {
         for _ in 0..(self.height) {
             self.write(format!("{}\n", clear::CurrentLine))?;
         }
-        self.origin.1 = min(self.origin.1, terminal_size().unwrap().1 - self.height);
+        let rows = self.stdout.size()?.1;
+        self.origin.1 = min(self.origin.1, rows.saturating_sub(self.height));
         self.goto_origin()?;
         Ok(self)
     }
@@ -77,15 +89,16 @@ impl Dropdown {
     }
 
     pub fn teardown(&mut self) -> Result<&mut Self> {
-        util::redraw_window()?;
+        self.stdout.redraw()?;
         self.reset()?;
         let start = self.start;
         self.write(start)
     }
 }
 
-impl Drop for Dropdown {
+impl<T: Terminal> Drop for Dropdown<T> {
     fn drop(&mut self) {
-        self.teardown().unwrap();
+        // Nothing can be done about a terminal that went away mid-prompt
+        let _ = self.teardown();
     }
 }
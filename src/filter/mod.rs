@@ -1,13 +1,30 @@
 use std::cmp::Ordering;
+use ::util::DESCRIPTION_SEPARATOR;
 
 pub struct WeightedMatch {
     pub weight: f32,
     pub result: String,
     pub original: String,
+    /// Character offsets in the value of the characters the query matched
+    pub positions: Vec<usize>,
 }
 
 pub trait Filter {
     fn matched(query: &str, value: &str) -> Option<WeightedMatch>;
+
+    /// Matches only the value of a candidate, carrying its description
+    /// column along as it is
+    fn matched_candidate(query: &str, candidate: &str) -> Option<WeightedMatch> {
+        let idx = match candidate.find(DESCRIPTION_SEPARATOR) {
+            Some(idx) => idx,
+            None => return Self::matched(query, candidate),
+        };
+        Self::matched(query, &candidate[..idx]).map(|matched| WeightedMatch {
+            result: format!("{}{}", matched.result, &candidate[idx..]),
+            original: candidate.to_string(),
+            ..matched
+        })
+    }
 }
 
 impl WeightedMatch {
@@ -16,6 +33,26 @@ impl WeightedMatch {
     }
 }
 
+mod ranked;
 mod spaced;
 
+pub use self::ranked::{Ranked, rank};
 pub use self::spaced::SpacedFilter;
+
+#[cfg(test)]
+mod test {
+    use super::{Filter, SpacedFilter};
+    use ::readkeys::Printable;
+    use ::util::describe;
+
+    #[test]
+    fn test_matched_candidate_ignores_the_description() {
+        let candidate = describe("ls", "/usr/bin");
+        assert!(SpacedFilter::matched_candidate("usr", &candidate).is_none());
+
+        let matched = SpacedFilter::matched_candidate("ls", &candidate).unwrap();
+        assert_eq!(matched.result.without_escape_codes(), candidate);
+        assert_eq!(matched.weight, SpacedFilter::matched("ls", "ls").unwrap().weight);
+        assert_eq!(matched.positions, vec![0, 1]);
+    }
+}
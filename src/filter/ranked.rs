@@ -0,0 +1,113 @@
+use ::completer::Completer;
+use ::filter::Filter;
+use ::readkeys::Printable;
+use std::thread;
+use std::time::Duration;
+
+/// How often `rank` checks on a completer that is still streaming
+const STREAM_WAIT: Duration = Duration::from_millis(10);
+
+/// A candidate as ranked for a query, without a terminal to show it on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ranked {
+    pub value: String,
+    pub description: Option<String>,
+    /// The weight the completer ranked it by, including any frecency
+    pub score: f32,
+    /// Character offsets in the value that the filter matched
+    pub positions: Vec<usize>,
+}
+
+/// Waits for a streaming completer to finish, then ranks its candidates
+/// for `query` best first
+pub fn rank<C: Completer, F: Filter>(completer: &mut C, query: &str) -> Vec<Ranked> {
+    while completer.streaming() {
+        if !completer.poll() {
+            thread::sleep(STREAM_WAIT);
+        }
+    }
+
+    completer.matches::<F>(query).into_iter().map(|m| Ranked {
+        value: completer.resolve(&m.result.value()),
+        description: m.result.description().map(|description| description.without_escape_codes()),
+        score: m.weight,
+        positions: m.positions,
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::rank;
+    use ::completer::{JumpCompleter, ListCompleter, MixedCompleter, Mode};
+    use ::filter::{Filter, SpacedFilter};
+    use ::frecency::{Database, frecency, now};
+    use ::frecency::rank as frecency_rank;
+    use ::util::{fixture, lock_cwd, path_string};
+    use std::fs::{self, File};
+
+    #[test]
+    fn test_rank() {
+        let choices = vec!["docs/readme.md", "src/main.rs", "src/mod.rs"];
+        let mut completer = ListCompleter::new(choices.into_iter().map(str::to_string).collect());
+        let ranked = rank::<_, SpacedFilter>(&mut completer, "main");
+
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].value, "src/main.rs");
+        assert_eq!(ranked[0].positions, vec![4, 5, 6, 7]);
+        assert!(ranked[0].score > 0.0);
+    }
+
+    #[test]
+    fn test_rank_files() {
+        let _cwd = lock_cwd();
+        let dir = fixture("rank-files");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        for path in &["src/main.rs", "src/mixed.rs", "src/mix_expanded.rs", "README.md"] {
+            File::create(dir.join(path)).unwrap();
+        }
+        let root = path_string(&dir);
+        let mut completer = MixedCompleter::default();
+        completer.mode(Mode::Recursive);
+
+        let ranked = rank::<_, SpacedFilter>(&mut completer, &format!("{}/mai", root));
+        let values: Vec<_> = ranked.iter().map(|ranked| &*ranked.value).collect();
+        assert_eq!(values, vec![format!("{}/src/main.rs", root)]);
+
+        // A run of matches beats the same letters scattered
+        let ranked = rank::<_, SpacedFilter>(&mut completer, &format!("{}/mixed", root));
+        let values: Vec<_> = ranked.iter().map(|ranked| &*ranked.value).collect();
+        assert_eq!(values, vec![format!("{}/src/mixed.rs", root), format!("{}/src/mix_expanded.rs", root)]);
+        let length = root.chars().count();
+        let name = length + "/src/".len();
+        assert_eq!(ranked[0].positions, (0..length + 1).chain(name..name + 5).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_rank_by_frecency() {
+        let _cwd = lock_cwd();
+        let dir = fixture("rank-frecency");
+        for name in &["project-a", "project-b"] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        let mut database = Database::open_path(Some(dir.join("jump")));
+        database.add(dir.join("project-a"));
+        for _ in 0..5 {
+            database.add(dir.join("project-b"));
+        }
+        let mut completer = JumpCompleter::new(database, MixedCompleter::default());
+
+        let ranked = rank::<_, SpacedFilter>(&mut completer, "project");
+        let values: Vec<_> = ranked.iter().map(|ranked| ranked.value.clone()).collect();
+        assert_eq!(values, vec![
+            format!("{}/project-b/", path_string(&dir)),
+            format!("{}/project-a/", path_string(&dir)),
+        ]);
+
+        // Scores include the frecency the ranking used, not just the match
+        for (ranked, count) in ranked.iter().zip(&[5, 1]) {
+            let weight = SpacedFilter::matched("project", &ranked.value).unwrap().weight;
+            let expected = frecency_rank(weight, frecency(*count, now(), now()));
+            assert!((ranked.score - expected).abs() < 1e-4, "{} != {}", ranked.score, expected);
+        }
+    }
+}
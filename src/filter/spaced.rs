@@ -21,6 +21,7 @@ impl SpacedFilter {
         let mut run = true;
         let mut weight = 0.0;
         let mut first_char = None;
+        let mut positions = vec![];
 
         for (i, c_value) in value.to_string().chars().enumerate() {
             let c_value_lower: String = c_value.to_lowercase().collect();
@@ -31,6 +32,7 @@ impl SpacedFilter {
                     c_query_opt = query.pop();
                     weight += if run { 10.0 } else { 1.0 };
                     if first_char.is_none() { first_char = Some(i); }
+                    positions.push(i);
                     run = true;
                 } else {
                     run = false;
@@ -44,6 +46,7 @@ impl SpacedFilter {
 
         if result.starts_with("./") {
             result = result[2..].to_string();
+            positions = positions.into_iter().map(|position| position - 2).collect();
         }
 
         if query.is_empty() && c_query_opt.is_none() {
@@ -54,15 +57,19 @@ impl SpacedFilter {
             };
             weight /= length_penalty * first_char_penalty;
 
-            Some(WeightedMatch { result, weight, original })
+            Some(WeightedMatch { result, weight, original, positions })
         } else {
             None
         }
     }
 
     fn offset_match(query: &str, value: &str, offset: usize) -> Option<WeightedMatch> {
-        SpacedFilter::weigh(query, &value[offset..])
-            .map(|m| WeightedMatch { result: format!("{}{}", &value[..offset], m.result), ..m })
+        let skipped = value[..offset].chars().count();
+        SpacedFilter::weigh(query, &value[offset..]).map(|m| WeightedMatch {
+            result: format!("{}{}", &value[..offset], m.result),
+            positions: m.positions.iter().map(|position| position + skipped).collect(),
+            ..m
+        })
     }
 }
 
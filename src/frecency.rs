@@ -0,0 +1,167 @@
+use ::errors::Result;
+use ::util::{data_dir, path_string};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+/// Once the counts add up to this, they are all aged so that directories
+/// that are no longer visited eventually drop out
+const MAX_TOTAL: u32 = 10_000;
+
+/// Seconds since the epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0)
+}
+
+/// Scores how often and how recently something was used, weighting recent
+/// use the same way zoxide and z do
+pub fn frecency(count: u32, last_used: u64, now: u64) -> f32 {
+    let age = now.saturating_sub(last_used);
+    let recency = if age < HOUR {
+        4.0
+    } else if age < DAY {
+        2.0
+    } else if age < WEEK {
+        0.5
+    } else {
+        0.25
+    };
+    count as f32 * recency
+}
+
+/// Combines a filter weight with a frecency score. The frecency is damped
+/// logarithmically so a much better match still beats a popular one.
+pub fn rank(weight: f32, frecency: f32) -> f32 {
+    weight * (1.0 + (1.0 + frecency).ln())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub count: u32,
+    pub last_used: u64,
+}
+
+/// Directories the user has visited, stored one per line as
+/// `<path>\t<count>\t<last used>`
+pub struct Database {
+    path: Option<PathBuf>,
+    entries: HashMap<PathBuf, Entry>,
+}
+
+fn parse_entry(line: &str) -> Option<Entry> {
+    let mut fields = line.rsplitn(3, '\t');
+    let last_used = fields.next()?.parse().ok()?;
+    let count = fields.next()?.parse().ok()?;
+    let path = PathBuf::from(fields.next()?);
+    Some(Entry { path, count, last_used })
+}
+
+impl Database {
+    pub fn open() -> Database {
+        Database::open_path(data_dir().map(|dir| dir.join("directories")))
+    }
+
+    pub fn open_path(path: Option<PathBuf>) -> Database {
+        let mut contents = String::new();
+        if let Some(ref path) = path {
+            let _ = File::open(path).and_then(|mut file| file.read_to_string(&mut contents));
+        }
+        let entries = contents.lines()
+            .filter_map(parse_entry)
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        Database { path, entries }
+    }
+
+    pub fn add<P: AsRef<Path>>(&mut self, dir: P) {
+        let (dir, now) = (dir.as_ref().to_owned(), now());
+        let entry = self.entries.entry(dir.clone())
+            .or_insert(Entry { path: dir, count: 0, last_used: now });
+        entry.count += 1;
+        entry.last_used = now;
+        self.age();
+    }
+
+    fn age(&mut self) {
+        let total: u32 = self.entries.values().map(|entry| entry.count).sum();
+        if total > MAX_TOTAL {
+            for entry in self.entries.values_mut() {
+                entry.count = entry.count * 9 / 10;
+            }
+            self.entries.retain(|_, entry| entry.count > 0);
+        }
+    }
+
+    /// Entries whose directory still exists
+    pub fn entries(&self) -> Vec<&Entry> {
+        self.entries.values().filter(|entry| entry.path.is_dir()).collect()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write and rename so concurrent shells never read a partial file.
+        // Each process writes its own file so two saves can't interleave.
+        let temporary = path.with_extension(format!("{}.tmp", process::id()));
+        {
+            let mut file = File::create(&temporary)?;
+            for entry in self.entries.values() {
+                writeln!(file, "{}\t{}\t{}", path_string(&entry.path), entry.count, entry.last_used)?;
+            }
+        }
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{frecency, rank, parse_entry, Database, Entry, DAY, HOUR, MAX_TOTAL};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_frecency_prefers_recent() {
+        let now = 10 * DAY;
+        assert!(frecency(1, now - 1, now) > frecency(1, now - 2 * HOUR, now));
+        assert!(frecency(1, now - 2 * HOUR, now) > frecency(1, now - 3 * DAY, now));
+        assert!(frecency(10, now - 3 * DAY, now) > frecency(1, now - 3 * DAY, now));
+    }
+
+    #[test]
+    fn test_rank_preserves_order_for_equal_frecency() {
+        assert!(rank(2.0, 1.0) > rank(1.0, 1.0));
+        assert!(rank(1.0, 8.0) > rank(1.0, 1.0));
+    }
+
+    #[test]
+    fn test_parse_entry() {
+        let entry = Entry { path: PathBuf::from("/tmp/a\tb"), count: 3, last_used: 1500000000 };
+        assert_eq!(parse_entry("/tmp/a\tb\t3\t1500000000"), Some(entry));
+        assert_eq!(parse_entry("/tmp/a\t3"), None);
+    }
+
+    #[test]
+    fn test_database_ages_entries() {
+        let mut database = Database::open_path(None);
+        for _ in 0..MAX_TOTAL {
+            database.add("/");
+        }
+        database.add("/tmp");
+        assert_eq!(database.entries.len(), 1);
+        assert_eq!(database.entries[&PathBuf::from("/")].count, MAX_TOTAL * 9 / 10);
+    }
+}
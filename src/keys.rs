@@ -0,0 +1,211 @@
+use ::errors::Result;
+use ::readkeys::{key_name, parse_key};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use termion::event::Key;
+
+/// Where `Readkeys` gets its keys: the terminal, a channel, or a replayed
+/// recording
+pub trait KeySource {
+    /// Waits for the next key, giving up after `timeout` if there is one.
+    /// `Disconnected` means no more keys will come.
+    fn next_key(&mut self, timeout: Option<Duration>) -> ::std::result::Result<Key, RecvTimeoutError>;
+}
+
+impl KeySource for Receiver<Key> {
+    fn next_key(&mut self, timeout: Option<Duration>) -> ::std::result::Result<Key, RecvTimeoutError> {
+        match timeout {
+            Some(timeout) => self.recv_timeout(timeout),
+            None => self.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        }
+    }
+}
+
+/// What `async_keys` reads from the terminal. A read error ends the keys.
+impl KeySource for Receiver<io::Result<Key>> {
+    fn next_key(&mut self, timeout: Option<Duration>) -> ::std::result::Result<Key, RecvTimeoutError> {
+        let key = match timeout {
+            Some(timeout) => self.recv_timeout(timeout)?,
+            None => self.recv().map_err(|_| RecvTimeoutError::Disconnected)?,
+        };
+        key.map_err(|_| RecvTimeoutError::Disconnected)
+    }
+}
+
+/// Passes keys through from another source, writing each to a recording.
+/// Every line holds the milliseconds since the key before, a tab, and the
+/// key's name as `parse_key` reads it. Lines starting with `#` are notes,
+/// which is also how keys without a name are written down.
+pub struct Recorder<S: KeySource, W: Write = File> {
+    source: S,
+    recording: W,
+    last: Instant,
+}
+
+impl<S: KeySource> Recorder<S> {
+    pub fn create<P: AsRef<Path>>(path: P, source: S) -> Result<Self> {
+        Ok(Recorder::new(source, File::create(path)?))
+    }
+}
+
+impl<S: KeySource, W: Write> Recorder<S, W> {
+    pub fn new(source: S, recording: W) -> Self {
+        Recorder { source, recording, last: Instant::now() }
+    }
+
+    /// Adds a comment, such as the command line being recorded
+    pub fn note(&mut self, note: &str) -> Result<&mut Self> {
+        writeln!(self.recording, "# {}", note)?;
+        Ok(self)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.recording
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1_000_000
+}
+
+impl<S: KeySource, W: Write> KeySource for Recorder<S, W> {
+    fn next_key(&mut self, timeout: Option<Duration>) -> ::std::result::Result<Key, RecvTimeoutError> {
+        let key = self.source.next_key(timeout)?;
+        let now = Instant::now();
+        let delay = millis(now.duration_since(self.last));
+        self.last = now;
+
+        // A recording that can't be written shouldn't break the prompt
+        let name = key_name(key);
+        let _ = if name.is_empty() || name.contains(char::is_control) {
+            writeln!(self.recording, "# {}\tunnamed key {:?}", delay, key)
+        } else {
+            writeln!(self.recording, "{}\t{}", delay, name)
+        };
+        let _ = self.recording.flush();
+        Ok(key)
+    }
+}
+
+/// Plays back a recording made by `Recorder`, keeping its timing so
+/// streamed candidates arrive between the same keys
+pub struct Replay {
+    keys: VecDeque<(Duration, Key)>,
+    last: Option<Instant>,
+}
+
+impl Replay {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut recording = String::new();
+        File::open(path)?.read_to_string(&mut recording)?;
+        Ok(Replay::parse(&recording))
+    }
+
+    /// Skips lines that aren't a delay and a key, so a recording edited by
+    /// hand or made by a newer version still plays what it can
+    pub fn parse(recording: &str) -> Self {
+        let keys = recording.lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut fields = line.splitn(2, '\t');
+                let delay = fields.next().and_then(|delay| delay.parse().ok())?;
+                let key = fields.next().and_then(parse_key)?;
+                Some((Duration::from_millis(delay), key))
+            })
+            .collect();
+        Replay { keys, last: None }
+    }
+}
+
+impl KeySource for Replay {
+    fn next_key(&mut self, timeout: Option<Duration>) -> ::std::result::Result<Key, RecvTimeoutError> {
+        let (delay, key) = *self.keys.front().ok_or(RecvTimeoutError::Disconnected)?;
+        let last = *self.last.get_or_insert_with(Instant::now);
+        let elapsed = Instant::now().duration_since(last);
+
+        if delay > elapsed {
+            let remaining = delay - elapsed;
+            match timeout {
+                Some(timeout) if timeout < remaining => {
+                    thread::sleep(timeout);
+                    return Err(RecvTimeoutError::Timeout)
+                }
+                _ => thread::sleep(remaining),
+            }
+        }
+
+        self.keys.pop_front();
+        self.last = Some(Instant::now());
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{KeySource, Recorder, Replay};
+    use std::sync::mpsc::{channel, RecvTimeoutError};
+    use std::time::Duration;
+    use termion::event::Key;
+
+    #[test]
+    fn test_record_and_replay() {
+        let (tx, rx) = channel();
+        for key in [Key::Char('s'), Key::Ctrl('n'), Key::Char('\n')] {
+            tx.send(key).unwrap();
+        }
+        drop(tx);
+
+        let mut recorder = Recorder::new(rx, vec![]);
+        recorder.note("complesh --jump").unwrap();
+        while recorder.next_key(None).is_ok() {}
+        let recording = String::from_utf8(recorder.into_inner()).unwrap();
+
+        let lines: Vec<_> = recording.lines().map(|line| line.splitn(2, '\t').last().unwrap()).collect();
+        assert_eq!(lines, vec!["# complesh --jump", "s", "ctrl-n", "enter"]);
+
+        let mut replay = Replay::parse(&recording);
+        assert_eq!(replay.next_key(None), Ok(Key::Char('s')));
+        assert_eq!(replay.next_key(None), Ok(Key::Ctrl('n')));
+        assert_eq!(replay.next_key(None), Ok(Key::Char('\n')));
+        assert_eq!(replay.next_key(None), Err(RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn test_replay_waits_for_the_recorded_delay() {
+        let mut replay = Replay::parse("0\tx\n60000\ty\n");
+        assert_eq!(replay.next_key(Some(Duration::from_millis(1))), Ok(Key::Char('x')));
+        assert_eq!(replay.next_key(Some(Duration::from_millis(1))), Err(RecvTimeoutError::Timeout));
+    }
+
+    #[test]
+    fn test_record_unnamed_keys_as_notes() {
+        let (tx, rx) = channel();
+        for key in &[Key::Alt('\n'), Key::Char('\u{7}'), Key::Char('x')] {
+            tx.send(*key).unwrap();
+        }
+        drop(tx);
+
+        let mut recorder = Recorder::new(rx, vec![]);
+        while recorder.next_key(None).is_ok() {}
+        let recording = String::from_utf8(recorder.into_inner()).unwrap();
+        assert_eq!(recording.lines().count(), 3);
+        assert!(recording.lines().take(2).all(|line| line.starts_with("# ")));
+
+        let mut replay = Replay::parse(&recording);
+        assert_eq!(replay.next_key(None), Ok(Key::Char('x')));
+        assert_eq!(replay.next_key(None), Err(RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn test_replay_skips_bad_lines() {
+        let mut replay = Replay::parse("0\tx\nx\ty\n0\thyper-q\n\n0\tctrl-n\n");
+        assert_eq!(replay.next_key(None), Ok(Key::Char('x')));
+        assert_eq!(replay.next_key(None), Ok(Key::Ctrl('n')));
+        assert_eq!(replay.next_key(None), Err(RecvTimeoutError::Disconnected));
+    }
+}
@@ -3,17 +3,31 @@
 extern crate nix;
 extern crate nlp_tokenize;
 extern crate regex;
+#[macro_use] extern crate serde_json;
 extern crate termion;
+extern crate unicode_segmentation;
+extern crate unicode_width;
 extern crate walkdir;
 extern crate ignore;
 extern crate crossbeam;
+extern crate flate2;
+extern crate glob;
 extern crate rayon;
 
+pub mod accounts;
+pub mod bindings;
 pub mod completer;
+pub mod config;
 pub mod filter;
 pub mod dropdown;
 pub mod errors;
+pub mod frecency;
+pub mod keys;
+pub mod picker;
 pub mod readkeys;
 pub mod util;
 pub mod prompt;
+pub mod repository;
 pub mod ring_buffer;
+pub mod server;
+pub mod terminal;
@@ -3,32 +3,198 @@ extern crate complesh;
 extern crate termion;
 extern crate nix;
 extern crate glob;
+#[macro_use] extern crate serde_json;
 
 use clap::{Arg, App};
 use complesh::dropdown::Dropdown;
-use complesh::completer::{Completer, MixedCompleter, ListCompleter};
-use complesh::prompt::DropdownPrompt;
-use complesh::readkeys::Readkeys;
+use clap::ArgMatches;
+use complesh::bindings::Bindings;
+use complesh::completer::{Completer, CommandCompleter, HistoryCompleter, HostCompleter, JumpCompleter};
+use complesh::completer::{MixedCompleter, ListCompleter, ProcessCompleter, RemoteCompleter, Submodules, read_choices};
+use complesh::completer::{Accounts, OptionCompleter, TargetCompleter, UserCompleter};
+use complesh::completer::{Context, PluginCompleter};
+use complesh::config::Config;
+use complesh::filter::{SpacedFilter, rank};
+use complesh::frecency::Database;
+use complesh::prompt::{DropdownPrompt, Selection};
+use complesh::keys::{Recorder, Replay};
+use complesh::readkeys::{Readkeys, async_keys, key_name, parse_key};
+use complesh::server::Server;
 use complesh::errors::Result;
+use complesh::util::{canonicalize, expand_user};
+use std::env;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::stdout;
+use std::io::{stdin, stdout, BufReader};
+use std::path::Path;
+use std::process;
 use termion::color::{self, Blue, Fg};
+use termion::event::Key;
 
-fn run<C>(mut prompt: DropdownPrompt<C>, output_path: Option<&str>) -> Result<()>
+/// A candidate, or with --filter the ranked candidates, was printed
+const EXIT_ACCEPTED: i32 = 0;
+/// The typed input was taken as it is with Alt-Enter
+const EXIT_INPUT: i32 = 1;
+const EXIT_ERROR: i32 = 2;
+/// Esc, Ctrl-c or Ctrl-g, following the shell's 128 + SIGINT
+const EXIT_CANCELLED: i32 = 130;
+
+/// How the chosen completer is run, shared by every completer
+struct Session<'a> {
+    prompt: String,
+    height: u16,
+    input: String,
+    output_path: Option<&'a str>,
+    filter: Option<&'a str>,
+    scores: bool,
+    expect: Vec<Key>,
+    print_query: bool,
+    json: bool,
+    record: Option<&'a str>,
+    replay: Option<&'a str>,
+    bindings: Bindings,
+}
+
+/// Reads keys from the terminal, a replayed recording, or the terminal
+/// while recording them
+fn readkeys(session: &Session) -> Result<Readkeys> {
+    let input = session.input.clone();
+    Ok(match (session.replay, session.record) {
+        (Some(path), _) => Readkeys::with_source(input, Replay::open(path)?),
+        (None, Some(path)) => {
+            let mut recorder = Recorder::create(path, async_keys())?;
+            recorder.note(&*env::args().collect::<Vec<_>>().join(" "))?;
+            Readkeys::with_source(input, recorder)
+        }
+        (None, None) => Readkeys::new(input),
+    })
+}
+
+fn run<C>(mut completer: Box<C>, session: &Session) -> Result<i32>
     where C: Completer
 {
-    let completion = prompt.prompt()?.unwrap_or(String::new());
+    if let Some(query) = session.filter {
+        filter(&mut *completer, query, session)?;
+        return Ok(EXIT_ACCEPTED)
+    }
+
+    let completes_paths = completer.completes_paths();
+    // The dropdown asks the tty where the cursor is, so it goes first or
+    // the key reader would take the answer as keys
+    let output = Dropdown::new(session.height)?;
+    let mut input = readkeys(session)?;
+    input.expect(session.expect.clone()).bindings(session.bindings.clone());
+    let selection = DropdownPrompt::new(session.prompt.clone(), input, output, completer).pick()?;
+
+    let status = status(&selection);
+    let items = match selection {
+        Some(ref selection) if completes_paths => selection.items.clone(),
+        _ => vec![],
+    };
+    write_output(result(selection, status, session).as_bytes(), session.output_path)?;
+
+    // The completion is already out, so a database that can't be saved
+    // only costs jump completion this visit
+    for item in items {
+        if let Err(error) = record_directory(&item) {
+            writeln!(&mut std::io::stderr(), "Complesh Warning: couldn't record {}: {}", item, error).unwrap();
+        }
+    }
+    Ok(status)
+}
+
+fn status(selection: &Option<Selection>) -> i32 {
+    match *selection {
+        None => EXIT_CANCELLED,
+        Some(Selection { raw: true, .. }) => EXIT_INPUT,
+        Some(_) => EXIT_ACCEPTED,
+    }
+}
+
+/// The completion followed by a space for the shell to insert. With
+/// --print-query and --expect, the query and the name of the accepting
+/// key come first on lines of their own. --json reports all of it.
+fn result(selection: Option<Selection>, status: i32, session: &Session) -> String {
+    let completion = match selection {
+        Some(ref selection) => format!("{} ", selection.items.join(" ")),
+        None => String::new(),
+    };
+    let key = selection.as_ref().and_then(|selection| selection.key).map(key_name);
+    let query = selection.as_ref().map(|selection| selection.query.clone()).unwrap_or(String::new());
+
+    if session.json {
+        let status_name = match status {
+            EXIT_ACCEPTED => "accepted",
+            EXIT_INPUT    => "input",
+            _             => "cancelled",
+        };
+        let items = selection.map(|selection| selection.items).unwrap_or(vec![]);
+        return format!("{}\n", json!({ "status": status_name, "key": key, "query": query, "items": items }))
+    }
 
+    let mut lines = vec![];
+    if session.print_query {
+        lines.push(query);
+    }
+    if !session.expect.is_empty() {
+        lines.push(key.unwrap_or(String::new()));
+    }
+    lines.push(completion);
+    lines.join("\n")
+}
+
+/// Prints the ranked candidates for `query` one per line, preceded by
+/// their score and matched character offsets with --scores
+fn filter<C: Completer>(completer: &mut C, query: &str, session: &Session) -> Result<()> {
+    let mut lines = String::new();
+    for ranked in rank::<_, SpacedFilter>(completer, query) {
+        if session.scores {
+            let positions: Vec<_> = ranked.positions.iter().map(|p| p.to_string()).collect();
+            lines += &*format!("{:.4}\t{}\t", ranked.score, positions.join(","));
+        }
+        lines += &*format!("{}\n", ranked.value);
+    }
+    write_output(lines.as_bytes(), session.output_path)
+}
+
+fn write_output(bytes: &[u8], output_path: Option<&str>) -> Result<()> {
     if let Some(path) = output_path {
-        File::create(path).unwrap().write_all(completion.as_bytes()).unwrap();
+        File::create(path)?.write_all(bytes)?;
     } else {
-        stdout().write_all(completion.as_bytes()).unwrap();
+        stdout().write_all(bytes)?;
     }
+    Ok(())
+}
 
+/// Remembers accepted directories for jump completion
+fn record_directory(path: &str) -> Result<()> {
+    let path = canonicalize(expand_user(path.trim_end()));
+    if !path.as_os_str().is_empty() && path.is_dir() {
+        let mut database = Database::open();
+        database.add(path);
+        database.save()?;
+    }
     Ok(())
 }
 
+fn mixed_completer(matches: &ArgMatches) -> MixedCompleter {
+    let mut completer = MixedCompleter::default();
+    completer.submodules(match matches.value_of("SUBMODULES") {
+        Some("roots")   => Submodules::RootsOnly,
+        Some("current") => Submodules::Current,
+        _               => Submodules::Recurse,
+    });
+    if let Some(base) = matches.value_of("BASE") {
+        completer.base_branch(base);
+    }
+    completer.shell_variables(words(matches.value_of("VARIABLES")));
+    completer
+}
+
+fn words(value: Option<&str>) -> Vec<String> {
+    value.unwrap_or("").split_whitespace().map(str::to_string).collect()
+}
+
 fn main() {
     let matches = App::new("complesh")
         .version("0.1.0")
@@ -54,27 +220,318 @@ fn main() {
              .long("choices")
              .help("Whitespace delimited list of choices")
              .takes_value(true))
+        .arg(Arg::with_name("COMMAND")
+             .long("command")
+             .help("Complete a command name from $PATH and the shell"))
+        .arg(Arg::with_name("COMMAND_NAME")
+             .long("command-name")
+             .help("Command being completed, used to pick a completer")
+             .takes_value(true))
+        .arg(Arg::with_name("PREVIOUS_WORD")
+             .long("previous-word")
+             .help("Word before the one being completed")
+             .takes_value(true))
+        .arg(Arg::with_name("MINE")
+             .long("mine")
+             .help("Only complete the current user's processes"))
+        .arg(Arg::with_name("COMPLETER")
+             .long("completer")
+             .help("Completer to use instead of the one mapped to the command")
+             .possible_values(&["hosts", "remote", "processes", "users", "groups", "targets", "options"])
+             .takes_value(true))
+        .arg(Arg::with_name("JUMP")
+             .long("jump")
+             .help("Complete previously visited directories ranked by frecency"))
+        .arg(Arg::with_name("RECORD_DIR")
+             .long("record-dir")
+             .help("Record a visit to a directory for --jump and exit")
+             .takes_value(true))
+        .arg(Arg::with_name("HISTORY")
+             .long("history")
+             .help("Complete a full command line from the shell history"))
+        .arg(Arg::with_name("BUILTINS")
+             .long("builtins")
+             .help("Whitespace delimited list of shell builtins")
+             .takes_value(true))
+        .arg(Arg::with_name("ALIASES")
+             .long("aliases")
+             .help("Whitespace delimited list of shell aliases")
+             .takes_value(true))
+        .arg(Arg::with_name("FUNCTIONS")
+             .long("functions")
+             .help("Whitespace delimited list of shell functions")
+             .takes_value(true))
+        .arg(Arg::with_name("VARIABLES")
+             .long("variables")
+             .help("Whitespace delimited list of shell variables")
+             .takes_value(true))
+        .arg(Arg::with_name("BASE")
+             .long("base")
+             .help("Base branch for changed file completion, defaults to origin/HEAD")
+             .takes_value(true))
+        .arg(Arg::with_name("FILE")
+             .short("-f")
+             .long("file")
+             .help("Read choices from a file, or stdin for -, as they arrive")
+             .takes_value(true))
+        .arg(Arg::with_name("READ0")
+             .short("-0")
+             .long("read0")
+             .help("Choices read with --file are NUL delimited instead of newline delimited"))
+        .arg(Arg::with_name("FILTER")
+             .long("filter")
+             .help("Print the candidates ranked for a query instead of prompting")
+             .takes_value(true))
+        .arg(Arg::with_name("SCORES")
+             .long("scores")
+             .requires("FILTER")
+             .help("Precede each --filter result with its score and matched positions"))
+        .arg(Arg::with_name("SERVE")
+             .long("serve")
+             .help("Answer JSON-RPC complete requests on stdin, one per line, until it closes"))
+        .arg(Arg::with_name("EXPECT")
+             .long("expect")
+             .help("Comma separated keys, like ctrl-o,alt-e, that also accept and are printed first")
+             .takes_value(true))
+        .arg(Arg::with_name("PRINT_QUERY")
+             .long("print-query")
+             .help("Print the final query on the first line"))
+        .arg(Arg::with_name("JSON")
+             .long("json")
+             .help("Print the status, accepting key, query and selection as JSON"))
+        .arg(Arg::with_name("RECORD")
+             .long("record")
+             .help("Save the keys pressed and their timing to a file for --replay")
+             .takes_value(true))
+        .arg(Arg::with_name("REPLAY")
+             .long("replay")
+             .help("Play back keys saved with --record instead of reading the terminal")
+             .conflicts_with("RECORD")
+             .takes_value(true))
+        .arg(Arg::with_name("SUBMODULES")
+             .long("submodules")
+             .help("How git completion treats submodules")
+             .possible_values(&["recurse", "roots", "current"])
+             .takes_value(true))
         .get_matches();
 
+    if matches.is_present("SERVE") {
+        let stdin = stdin();
+        let mut server = Server::new(|| mixed_completer(&matches));
+        if let Err(error) = server.serve(BufReader::new(stdin), stdout()) {
+            writeln!(&mut std::io::stderr(), "Complesh Error: {}", error).unwrap();
+            process::exit(EXIT_ERROR)
+        }
+        return
+    }
+
+    if let Some(dir) = matches.value_of("RECORD_DIR") {
+        if let Err(error) = record_directory(dir) {
+            writeln!(&mut std::io::stderr(), "Complesh Error: {}", error).unwrap();
+            process::exit(EXIT_ERROR)
+        }
+        return
+    }
+
+    let mut expect = vec![];
+    for name in matches.value_of("EXPECT").unwrap_or("").split(',').filter(|name| !name.is_empty()) {
+        match parse_key(name) {
+            Some(key) => expect.push(key),
+            None => {
+                writeln!(&mut std::io::stderr(), "Complesh Error: unknown key: {}", name).unwrap();
+                process::exit(EXIT_ERROR)
+            }
+        }
+    }
+
     let height      = matches.value_of("HEIGHT").unwrap_or("128").parse()
         .expect("Height must but an integer between 0 and 65535.");
 
-    let beginning   = matches.value_of("INPUT").unwrap_or("").to_string();
-    let output_path = matches.value_of("OUTPUT");
-    let output      = Dropdown::new(height);
-    let input       = Readkeys::new(beginning.clone());
-    let prompt_str  = format!("{}complesh: {}", Fg(Blue), Fg(color::Reset));
+    let config = Config::load();
+    let bindings = Bindings::from_config(&config).unwrap_or_else(|error| {
+        writeln!(&mut std::io::stderr(), "Complesh Error: {}, using the default key bindings", error).unwrap();
+        Bindings::default()
+    });
+
+    let filter      = matches.value_of("FILTER");
+    let beginning   = matches.value_of("INPUT").or(filter).unwrap_or("").to_string();
+    let session     = Session {
+        prompt: format!("{}complesh: {}", Fg(Blue), Fg(color::Reset)),
+        height,
+        input: beginning.clone(),
+        output_path: matches.value_of("OUTPUT"),
+        filter,
+        scores: matches.is_present("SCORES"),
+        expect,
+        print_query: matches.is_present("PRINT_QUERY"),
+        json: matches.is_present("JSON"),
+        record: matches.value_of("RECORD"),
+        replay: matches.value_of("REPLAY"),
+        bindings,
+    };
 
-    let res = if let Some(choice_string) = matches.value_of("CHOICES") {
+    let previous = matches.value_of("PREVIOUS_WORD");
+    let command_name = matches.value_of("COMMAND_NAME");
+    // A plugin registered for the command takes over from the completers
+    // it would otherwise be mapped to, but not from --completer
+    let plugin = command_name.and_then(|command| config.plugin_for(command));
+    let mapped = command_name.and_then(|command| config.completer_for(command, previous));
+    let completer_name = matches.value_of("COMPLETER").or_else(|| {
+        if plugin.is_some() {
+            return None
+        }
+        mapped.or_else(|| {
+            if command_name.is_some() && beginning.starts_with('-') { Some("options") } else { None }
+        })
+    });
+    let delimiter = if matches.is_present("READ0") { b'\0' } else { b'\n' };
+
+    let res = if let Some(path) = matches.value_of("FILE") {
+        let incoming = if path == "-" {
+            Ok(read_choices(stdin(), delimiter))
+        } else {
+            File::open(path).map(|file| read_choices(file, delimiter))
+        };
+        match incoming {
+            Ok(incoming) => {
+                let completer = Box::new(ListCompleter::from_receiver(incoming));
+                run(completer, &session)
+            }
+            Err(error) => Err(error.into()),
+        }
+    } else if let Some(choice_string) = matches.value_of("CHOICES") {
         let choices = choice_string.split_whitespace().map(str::to_string).collect();
         let completer = Box::new(ListCompleter::new(choices));
-        run(DropdownPrompt::new(prompt_str, input, output, completer), output_path)
+        run(completer, &session)
+    } else if completer_name == Some("hosts") {
+        let completer = Box::new(HostCompleter::default());
+        run(completer, &session)
+    } else if completer_name == Some("remote") {
+        let completer = Box::new(RemoteCompleter::new(HostCompleter::default(), mixed_completer(&matches)));
+        run(completer, &session)
+    } else if completer_name == Some("processes") {
+        let mut completer = Box::new(ProcessCompleter::default());
+        completer.only_current_user(matches.is_present("MINE"));
+        run(completer, &session)
+    } else if completer_name == Some("users") {
+        let completer = Box::new(UserCompleter::new(Accounts::Users));
+        run(completer, &session)
+    } else if completer_name == Some("groups") {
+        let completer = Box::new(UserCompleter::new(Accounts::Groups));
+        run(completer, &session)
+    } else if completer_name == Some("options") {
+        let command = command_name.unwrap_or("");
+        let mut completer = Box::new(OptionCompleter::new(command));
+        completer.run_help(config.runs_help(command));
+        run(completer, &session)
+    } else if completer_name == Some("targets") {
+        let tool = Path::new(command_name.unwrap_or("make")).file_name()
+            .map(|name| name.to_string_lossy().to_string()).unwrap_or(String::new());
+        let completer = Box::new(TargetCompleter::new(tool, previous.map(str::to_string)));
+        run(completer, &session)
+    } else if let (Some(executable), Some(command)) = (plugin, command_name) {
+        let context = Context {
+            query: beginning.clone(),
+            command: Some(command.to_string()),
+            previous: previous.map(str::to_string),
+        };
+        PluginCompleter::spawn(command, executable, context).and_then(|completer| {
+            run(Box::new(completer), &session)
+        })
+    } else if matches.is_present("JUMP") {
+        let completer = Box::new(JumpCompleter::new(Database::open(), mixed_completer(&matches)));
+        run(completer, &session)
+    } else if matches.is_present("HISTORY") {
+        let completer = Box::new(HistoryCompleter::default());
+        run(completer, &session)
+    } else if matches.is_present("COMMAND") {
+        let mut completer = Box::new(CommandCompleter::default());
+        completer
+            .builtins(words(matches.value_of("BUILTINS")))
+            .aliases(words(matches.value_of("ALIASES")))
+            .functions(words(matches.value_of("FUNCTIONS")));
+        run(completer, &session)
     } else {
-        let completer = Box::new(MixedCompleter::default());
-        run(DropdownPrompt::new(prompt_str, input, output, completer), output_path)
+        let completer = Box::new(mixed_completer(&matches));
+        run(completer, &session)
     };
 
-    if let Err(error) = res {
-        writeln!(&mut std::io::stderr(), "Complesh Error: {}", error).unwrap();
+    match res {
+        Ok(status) => process::exit(status),
+        Err(error) => {
+            writeln!(&mut std::io::stderr(), "Complesh Error: {}", error).unwrap();
+            process::exit(EXIT_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EXIT_ACCEPTED, EXIT_CANCELLED, EXIT_INPUT, Session, result, status};
+    use complesh::bindings::Bindings;
+    use complesh::prompt::Selection;
+    use serde_json::{self, Value};
+    use termion::event::Key;
+
+    fn session(expect: Vec<Key>, json: bool) -> Session<'static> {
+        Session {
+            prompt: String::new(),
+            height: 10,
+            input: String::new(),
+            output_path: None,
+            filter: None,
+            scores: false,
+            expect,
+            print_query: true,
+            json,
+            record: None,
+            replay: None,
+            bindings: Bindings::default(),
+        }
+    }
+
+    fn selection(items: &[&str], key: Key, query: &str, raw: bool) -> Option<Selection> {
+        let items = items.iter().map(|item| item.to_string()).collect();
+        Some(Selection { items, key: Some(key), query: query.to_string(), raw })
+    }
+
+    fn json(selection: Option<Selection>) -> (i32, Value) {
+        let status = status(&selection);
+        (status, serde_json::from_str(&result(selection, status, &session(vec![], true))).unwrap())
+    }
+
+    #[test]
+    fn test_cancelled() {
+        assert_eq!(status(&None), EXIT_CANCELLED);
+        assert_eq!(result(None, EXIT_CANCELLED, &session(vec![Key::Ctrl('o')], false)), "\n\n");
+
+        let (status, output) = json(None);
+        assert_eq!(status, EXIT_CANCELLED);
+        assert_eq!(output["status"], "cancelled");
+        assert_eq!(output["key"], Value::Null);
+    }
+
+    #[test]
+    fn test_accepted() {
+        let accepted = selection(&["src/", "README.md"], Key::Ctrl('o'), "r", false);
+        assert_eq!(status(&accepted), EXIT_ACCEPTED);
+        let output = result(accepted.clone(), EXIT_ACCEPTED, &session(vec![Key::Ctrl('o')], false));
+        assert_eq!(output, "r\nctrl-o\nsrc/ README.md ");
+
+        let (status, output) = json(accepted);
+        assert_eq!(status, EXIT_ACCEPTED);
+        assert_eq!(output, json!({ "status": "accepted", "key": "ctrl-o", "query": "r", "items": ["src/", "README.md"] }));
+    }
+
+    #[test]
+    fn test_accepted_raw() {
+        let raw = selection(&["REA"], Key::Alt('\r'), "REA", true);
+        assert_eq!(status(&raw), EXIT_INPUT);
+        assert_eq!(result(raw.clone(), EXIT_INPUT, &session(vec![], false)), "REA\nREA ");
+
+        let (status, output) = json(raw);
+        assert_eq!(status, EXIT_INPUT);
+        assert_eq!(output["status"], "input");
+        assert_eq!(output["key"], "alt-enter");
     }
 }
@@ -0,0 +1,191 @@
+use ::bindings::Bindings;
+use ::completer::ListCompleter;
+use ::dropdown::Dropdown;
+use ::errors::Result;
+use ::filter::{Filter, SpacedFilter};
+use ::prompt::{DropdownPrompt, Selection};
+use ::readkeys::Readkeys;
+use ::terminal::Terminal;
+use std::marker::PhantomData;
+use termion::event::Key;
+
+/// Lets a program ask the user to pick from a list, drawing on the
+/// controlling terminal so its own stdin and stdout are left alone:
+///
+/// ```no_run
+/// use complesh::picker::Picker;
+///
+/// match Picker::new().items(vec!["apple", "banana"]).height(10).run() {
+///     Ok(Some(selection)) => println!("{}", selection.items.join(" ")),
+///     Ok(None) => println!("nothing picked"),
+///     Err(error) => eprintln!("can't pick: {}", error),
+/// }
+/// ```
+pub struct Picker<F: Filter = SpacedFilter> {
+    items: Vec<String>,
+    query: String,
+    height: u16,
+    multi: bool,
+    select_one: bool,
+    expect: Vec<Key>,
+    bindings: Bindings,
+    filter: PhantomData<F>,
+}
+
+impl Default for Picker {
+    fn default() -> Self {
+        Picker {
+            items: vec![],
+            query: String::new(),
+            height: 10,
+            multi: false,
+            select_one: false,
+            expect: vec![],
+            bindings: Bindings::default(),
+            filter: PhantomData,
+        }
+    }
+}
+
+impl Picker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<F: Filter> Picker<F> {
+    /// Adds candidates. Repeats are listed once, under the first of them
+    pub fn items<I, S>(mut self, items: I) -> Self where I: IntoIterator<Item=S>, S: Into<String> {
+        self.items.extend(items.into_iter().map(Into::into));
+        self
+    }
+
+    /// Ranks candidates with `G` instead of `SpacedFilter`
+    pub fn filter<G: Filter>(self) -> Picker<G> {
+        let Picker { items, query, height, multi, select_one, expect, bindings, .. } = self;
+        Picker { items, query, height, multi, select_one, expect, bindings, filter: PhantomData }
+    }
+
+    /// Text the prompt starts with
+    pub fn query<S: Into<String>>(mut self, query: S) -> Self {
+        self.query = query.into();
+        self
+    }
+
+    /// Most lines the dropdown takes, including the prompt
+    pub fn height(mut self, height: u16) -> Self {
+        self.height = height;
+        self
+    }
+
+    /// Lets tab mark several items to pick at once
+    pub fn multi(mut self, multi: bool) -> Self {
+        self.multi = multi;
+        self
+    }
+
+    /// Picks a lone matching item without showing the prompt
+    pub fn select_one(mut self, select_one: bool) -> Self {
+        self.select_one = select_one;
+        self
+    }
+
+    /// Keys that also accept, reported back in the selection
+    pub fn expect<I: IntoIterator<Item=Key>>(mut self, keys: I) -> Self {
+        self.expect.extend(keys);
+        self
+    }
+
+    /// Key bindings to use instead of the emacs-style defaults, such as
+    /// `Bindings::vi()` or those read from the user's config
+    pub fn bindings(mut self, bindings: Bindings) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    /// Shows the picker, returning `None` if the user backed out. Fails
+    /// without asking when there is no terminal to draw on.
+    pub fn run(self) -> Result<Option<Selection>> {
+        let dropdown = Dropdown::new(self.height)?;
+        let readkeys = Readkeys::new(self.query.clone());
+        self.pick(dropdown, readkeys)
+    }
+
+    fn pick<T: Terminal>(self, dropdown: Dropdown<T>, mut readkeys: Readkeys) -> Result<Option<Selection>> {
+        let completer = Box::new(ListCompleter::new(self.items));
+        readkeys.expect(self.expect).bindings(self.bindings);
+        let mut prompt = DropdownPrompt::new(String::new(), readkeys, dropdown, completer)
+            .filter::<F>();
+        prompt.multi(self.multi).auto_accept(self.select_one);
+        prompt.pick()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Picker;
+    use ::dropdown::Dropdown;
+    use ::filter::{Filter, WeightedMatch};
+    use ::prompt::Selection;
+    use ::readkeys::Readkeys;
+    use ::terminal::VirtualScreen;
+    use termion::event::Key;
+
+    /// Matches only candidates starting with the query
+    struct PrefixFilter;
+
+    impl Filter for PrefixFilter {
+        fn matched(query: &str, value: &str) -> Option<WeightedMatch> {
+            if !value.starts_with(query) {
+                return None
+            }
+            Some(WeightedMatch {
+                weight: 1.0,
+                result: value.to_string(),
+                original: value.to_string(),
+                positions: (0..query.chars().count()).collect(),
+            })
+        }
+    }
+
+    fn pick<F: Filter>(picker: Picker<F>, keys: Vec<Key>) -> Option<Selection> {
+        let screen = VirtualScreen::new(30, 10);
+        let dropdown = Dropdown::with_terminal(screen, picker.height).unwrap();
+        let readkeys = Readkeys::scripted(picker.query.clone(), keys);
+        picker.pick(dropdown, readkeys).unwrap()
+    }
+
+    fn items(selection: Option<Selection>) -> Vec<String> {
+        selection.map(|selection| selection.items).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_multi() {
+        let picker = || Picker::new().items(vec!["alpha", "beta", "gamma"]);
+        let keys = vec![Key::Char('\t'), Key::Char('\t'), Key::Char('\n')];
+        assert_eq!(items(pick(picker().multi(true), keys.clone())), vec!["alpha", "beta"]);
+        assert_eq!(items(pick(picker(), keys)).len(), 1);
+    }
+
+    #[test]
+    fn test_select_one() {
+        let picker = || Picker::new().items(vec!["alpha", "beta"]).query("alp");
+        let selection = pick(picker().select_one(true), vec![]).unwrap();
+        assert_eq!((selection.items, selection.key), (vec!["alpha".to_string()], None));
+        assert_eq!(pick(picker(), vec![]), None);
+    }
+
+    #[test]
+    fn test_filter() {
+        let picker = || Picker::new().items(vec!["ab", "ba"]).query("b").filter::<PrefixFilter>();
+        assert_eq!(items(pick(picker(), vec![Key::Char('\n')])), vec!["ba"]);
+        assert_eq!(items(pick(picker(), vec![Key::Down, Key::Char('\n')])), vec!["ba"]);
+    }
+
+    #[test]
+    fn test_items_repeat_once() {
+        let picker = Picker::new().items(vec!["alpha", "beta", "alpha"]);
+        let keys = vec![Key::Down, Key::Down, Key::Char('\n')];
+        assert_eq!(items(pick(picker, keys)), vec!["alpha"]);
+    }
+}
@@ -1,33 +1,89 @@
+use ::bindings::{Action, Keymap};
 use ::completer::Completer;
 use ::dropdown::Dropdown;
 use ::errors::Result;
-use ::filter::SpacedFilter;
+use ::filter::{Filter, SpacedFilter};
 use ::readkeys::{Readkeys, ReadEvent, Printable};
 use ::ring_buffer::RingBuffer;
+use ::terminal::{Terminal, Tty};
+use std::marker::PhantomData;
 use std::path::PathBuf;
+use std::time::Duration;
+use ::util::DESCRIPTION_SEPARATOR;
 use termion::clear;
 use termion::color::{self, Blue, Fg};
+use termion::style::{self, Faint};
 use termion::event::Key;
 
-pub struct DropdownPrompt<C: Completer> {
-    dropdown: Dropdown,
+/// How often the dropdown redraws while candidates are streaming in
+const STREAM_REFRESH: Duration = Duration::from_millis(50);
+
+/// What the prompt was accepted with
+#[derive(Clone, Debug, PartialEq)]
+pub struct Selection {
+    /// The marked candidates in the order they were marked, or else the
+    /// one under the cursor
+    pub items: Vec<String>,
+    /// The key that accepted, or `None` when the only candidate was taken
+    /// without asking
+    pub key: Option<Key>,
+    /// What had been typed
+    pub query: String,
+    /// Whether the query was taken as typed rather than a candidate
+    pub raw: bool,
+}
+
+pub struct DropdownPrompt<C: Completer, F: Filter = SpacedFilter, T: Terminal = Tty> {
+    dropdown: Dropdown<T>,
     prompt: String,
     readkeys: Readkeys,
     completer: Box<C>,
     values: RingBuffer<String>,
+    marked: Vec<String>,
+    multi: bool,
+    auto_accept: bool,
+    filter: PhantomData<F>,
+}
+
+impl<C, T> DropdownPrompt<C, SpacedFilter, T> where C: Completer, T: Terminal {
+    pub fn new(prompt: String, readkeys: Readkeys, dropdown: Dropdown<T>, completer: Box<C>) -> Self {
+        Self {
+            values: RingBuffer::new(),
+            marked: vec![],
+            multi: false,
+            auto_accept: true,
+            filter: PhantomData,
+            prompt, readkeys, dropdown, completer
+        }
+    }
 }
 
-impl<C> DropdownPrompt<C> where C: Completer {
-    pub fn new(prompt: String, readkeys: Readkeys, dropdown: Dropdown, completer: Box<C>) -> Self {
-        Self { values: RingBuffer::new(), prompt, readkeys, dropdown, completer }
+impl<C, F, T> DropdownPrompt<C, F, T> where C: Completer, F: Filter, T: Terminal {
+    /// Ranks candidates with `G` instead
+    pub fn filter<G: Filter>(self) -> DropdownPrompt<C, G, T> {
+        let DropdownPrompt { dropdown, prompt, readkeys, completer, values, marked, multi, auto_accept, .. } = self;
+        DropdownPrompt { dropdown, prompt, readkeys, completer, values, marked, multi, auto_accept, filter: PhantomData }
+    }
+
+    /// Lets tab mark several candidates to accept together
+    pub fn multi(&mut self, multi: bool) -> &mut Self {
+        self.multi = multi;
+        self
+    }
+
+    /// Whether a lone candidate is accepted before the prompt is shown
+    pub fn auto_accept(&mut self, auto_accept: bool) -> &mut Self {
+        self.auto_accept = auto_accept;
+        self
     }
 
     fn current(&self) -> String {
-        self.values.current().unwrap_or(&self.readkeys.value).clone().without_escape_codes()
+        let selected = self.values.current().unwrap_or(&self.readkeys.value).value();
+        self.completer.resolve(&*selected)
     }
 
     fn complete(&mut self) {
-        self.values = self.completer.complete::<SpacedFilter>(&self.readkeys.value);
+        self.values = self.completer.complete::<F>(&self.readkeys.value);
     }
 
     fn max_lines(&self) -> usize {
@@ -35,7 +91,8 @@ impl<C> DropdownPrompt<C> where C: Completer {
     }
 
     fn update_prompt(&mut self) {
-        self.prompt = format!("{}{}: {}", Fg(Blue), self.completer.label(), Fg(color::Reset));
+        let state = if self.readkeys.keymap() == Keymap::Normal { " [normal]" } else { "" };
+        self.prompt = format!("{}{}{}: {}", Fg(Blue), self.completer.label(), state, Fg(color::Reset));
     }
 
     fn prompt_line(&mut self) -> String {
@@ -46,7 +103,7 @@ impl<C> DropdownPrompt<C> where C: Completer {
         self.update_prompt();
         let prompt_line = self.prompt_line();
         self.dropdown.goto_origin()?.write(prompt_line)?.flush()?;
-        let cursor = self.readkeys.cursor;
+        let cursor = self.readkeys.cursor_width();
         self.dropdown.set_cursor((self.prompt.width() + cursor) as u16)?;
         Ok(())
     }
@@ -55,10 +112,19 @@ impl<C> DropdownPrompt<C> where C: Completer {
         let mut n_lines = 0;
         let lines = self.values.iter();
         let max_lines = self.max_lines();
+        let width = self.dropdown.width()? as usize;
 
         for line in lines.take(max_lines) {
-            let prefix = if n_lines == 0 {"-> "} else {"   "};
-            self.dropdown.writeln(format!("{}{}", prefix, line))?;
+            let arrow = if n_lines == 0 {"->"} else {"  "};
+            let marked = self.marked.contains(&self.completer.resolve(&*line.value()));
+            let prefix = format!("{}{}", arrow, if marked {"*"} else {" "});
+            let mut columns = line.splitn(2, DESCRIPTION_SEPARATOR);
+            let value = columns.next().unwrap_or("");
+            let line = match columns.next() {
+                Some(description) => format!("{}  {}{}{}", value, Faint, description, style::Reset),
+                None => value.to_string(),
+            };
+            self.dropdown.writeln(format!("{}{}", prefix, line).truncated(width))?;
             n_lines += 1;
         }
 
@@ -70,7 +136,16 @@ impl<C> DropdownPrompt<C> where C: Completer {
 
     fn prompt_next<'a>(&'a mut self) -> Result<&'a ReadEvent> {
         self.render()?;
-        Ok(self.readkeys.recv())
+        if !self.completer.streaming() {
+            return Ok(self.readkeys.recv())
+        }
+        while self.readkeys.recv_timeout(STREAM_REFRESH).is_none() {
+            if self.completer.poll() {
+                self.complete();
+                self.render()?;
+            }
+        }
+        Ok(self.readkeys.last_event())
     }
 
     fn render(&mut self) -> Result<()> {
@@ -78,12 +153,26 @@ impl<C> DropdownPrompt<C> where C: Completer {
         self.render_prompt()
     }
 
-    fn padded(&self) -> String {
-        format!("{} ", self.current())
+    fn select(&self, key: Option<Key>) -> Selection {
+        let items = if self.marked.is_empty() { vec![self.current()] } else { self.marked.clone() };
+        Selection { items, key, query: self.readkeys.value.clone(), raw: false }
     }
 
-    fn readkeys_padded(&self) -> String {
-        format!("{} ", self.readkeys.value)
+    fn select_query(&self, key: Key) -> Selection {
+        let query = self.readkeys.value.clone();
+        Selection { items: vec![query.clone()], key: Some(key), query, raw: true }
+    }
+
+    fn toggle_mark(&mut self) {
+        if self.values.current().is_none() {
+            return
+        }
+        let current = self.current();
+        match self.marked.iter().position(|marked| *marked == current) {
+            Some(idx) => { self.marked.remove(idx); },
+            None => self.marked.push(current),
+        }
+        self.values.forward();
     }
 
     fn toggle_mode(&mut self) {
@@ -100,37 +189,173 @@ impl<C> DropdownPrompt<C> where C: Completer {
         if PathBuf::from(&current).is_dir() {
             self.readkeys.set_value(current)
         } else if let Some(first) = self.values.iter().next() {
-            self.readkeys.set_value(first.without_escape_codes())
+            self.readkeys.set_value(self.completer.resolve(&*first.value()))
         }
         self.complete()
     }
 
+    /// Runs the prompt for a shell, returning the accepted text followed
+    /// by a space, or `None` if it was abandoned
     pub fn prompt(&mut self) -> Result<Option<String>> {
+        Ok(self.pick()?.map(|selection| format!("{} ", selection.items.join(" "))))
+    }
+
+    /// Runs the prompt until something is accepted, or `None` if it was
+    /// abandoned
+    pub fn pick(&mut self) -> Result<Option<Selection>> {
         self.complete();
 
         // If there's only one option on the first complete, then
         // assume it's correct
-        if self.singular_file() {
-            return Ok(Some(self.padded()))
+        if self.auto_accept && !self.completer.streaming() && self.singular_file() {
+            return Ok(Some(self.select(None)))
         }
 
         self.dropdown.reset()?;
         loop {
-            match *self.prompt_next()? {
-                ReadEvent::Exit                        => return Ok(None),
-                ReadEvent::Submit                      => return Ok(Some(self.padded())),
-                ReadEvent::Key(Key::Ctrl('j'))         => return Ok(Some(self.readkeys_padded())),
-                ReadEvent::Key(Key::Ctrl('n'))         => self.values.forward(),
-                ReadEvent::Key(Key::Alt('n'))          => self.values.forward(),
-                ReadEvent::Key(Key::Down)              => self.values.forward(),
-                ReadEvent::Key(Key::Ctrl('p'))         => self.values.back(),
-                ReadEvent::Key(Key::Alt('p'))          => self.values.back(),
-                ReadEvent::Key(Key::Up)                => self.values.back(),
-                ReadEvent::Key(Key::Null)              => self.toggle_mode(),
-                ReadEvent::Tab if self.singular_file() => return Ok(Some(self.padded())),
-                ReadEvent::Tab                         => self.tab_to_dir(),
-                _                                      => self.complete(),
+            let event = *self.prompt_next()?;
+            match event {
+                ReadEvent::Exit                                  => return Ok(None),
+                ReadEvent::Accept(key)                           => return Ok(Some(self.select(Some(key)))),
+                ReadEvent::Action(Action::Accept, key)           => return Ok(Some(self.select(Some(key)))),
+                ReadEvent::Action(Action::AcceptRaw, key)        => return Ok(Some(self.select_query(key))),
+                ReadEvent::Action(Action::Next, _)               => self.values.forward(),
+                ReadEvent::Action(Action::Prev, _)               => self.values.back(),
+                ReadEvent::Action(Action::ToggleMode, _)         => self.toggle_mode(),
+                ReadEvent::Action(Action::ToggleMark, _)         => self.toggle_mark(),
+                ReadEvent::Action(Action::Complete, _) if self.multi => self.toggle_mark(),
+                ReadEvent::Action(Action::Complete, key) if self.singular_file() => {
+                    return Ok(Some(self.select(Some(key))))
+                }
+                ReadEvent::Action(Action::Complete, _)           => self.tab_to_dir(),
+                _                                                => self.complete(),
             };
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{DropdownPrompt, Selection};
+    use ::completer::{Completer, ListCompleter};
+    use ::dropdown::Dropdown;
+    use ::filter::SpacedFilter;
+    use ::readkeys::Readkeys;
+    use ::terminal::VirtualScreen;
+    use std::io::Write;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::Duration;
+    use termion::event::Key;
+
+    type Prompt = DropdownPrompt<ListCompleter, SpacedFilter, VirtualScreen>;
+
+    fn prompt(screen: &VirtualScreen, height: u16, keys: Vec<Key>) -> Prompt {
+        let choices = vec!["alpha", "beta", "gamma", "delta"].into_iter().map(str::to_string).collect();
+        let dropdown = Dropdown::with_terminal(screen.clone(), height).unwrap();
+        DropdownPrompt::new(String::new(), Readkeys::scripted("", keys), dropdown, Box::new(ListCompleter::new(choices)))
+    }
+
+    fn shell(width: u16, height: u16, row: u16) -> VirtualScreen {
+        let mut screen = VirtualScreen::new(width, height).with_cursor(1, row);
+        write!(screen, "$ vim ").unwrap();
+        screen
+    }
+
+    #[test]
+    fn test_render_below_the_command_line() {
+        let screen = shell(30, 10, 1);
+        let mut prompt = prompt(&screen, 6, vec![Key::Down]);
+        assert_eq!(prompt.pick().unwrap(), None);
+
+        assert_eq!(screen.snapshot(), [
+            "$ vim",
+            "list:",
+            "-> beta",
+            "   gamma",
+            "   delta",
+            "   alpha",
+        ].join("\n"));
+        assert_eq!(screen.cursor(), (7, 2));
+    }
+
+    #[test]
+    fn test_cut_long_descriptions_to_the_width() {
+        let screen = shell(20, 10, 1);
+        let choices = vec!["alpha\tthe first letter of the greek alphabet", "beta\tsecond"];
+        let choices = choices.into_iter().map(str::to_string).collect();
+        let dropdown = Dropdown::with_terminal(screen.clone(), 4).unwrap();
+        let readkeys = Readkeys::scripted("", vec![]);
+        let mut prompt = DropdownPrompt::new(String::new(), readkeys, dropdown, Box::new(ListCompleter::new(choices)));
+        assert_eq!(prompt.pick().unwrap(), None);
+
+        assert_eq!(screen.lines()[1..4].to_vec(), vec![
+            "list:",
+            "-> alpha  the first…",
+            "   beta  second",
+        ]);
+    }
+
+    #[test]
+    fn test_scroll_up_at_the_bottom() {
+        let screen = shell(30, 6, 6);
+        let mut prompt = prompt(&screen, 10, vec![Key::Char('t')]);
+        assert_eq!(prompt.pick().unwrap(), None);
+
+        assert_eq!(screen.lines(), vec![
+            "list: t",
+            "-> beta",
+            "   delta",
+            "",
+            "",
+            "",
+        ]);
+    }
+
+    #[test]
+    fn test_teardown_restores_the_cursor() {
+        let screen = shell(30, 10, 3);
+        {
+            let mut prompt = prompt(&screen, 6, vec![Key::Char('t'), Key::Char('\n')]);
+            let selection = prompt.pick().unwrap();
+            assert_eq!(selection, Some(Selection {
+                items: vec!["beta".to_string()],
+                key: Some(Key::Char('\n')),
+                query: "t".to_string(),
+                raw: false,
+            }));
+        }
+        assert_eq!(screen.snapshot(), "\n\n$ vim");
+        assert_eq!(screen.cursor(), (7, 3));
+        assert_eq!(screen.redraws(), 1);
+    }
+
+    #[test]
+    fn test_take_in_streamed_choices_while_waiting() {
+        let screen = shell(30, 10, 1);
+        let (choices, incoming) = channel();
+        let (keys, source) = channel();
+        choices.send("alpha".to_string()).unwrap();
+
+        // Down only moves within the candidates already shown, so landing
+        // on beta means it was taken in before any key arrived
+        let feeder = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            choices.send("beta".to_string()).unwrap();
+            drop(choices);
+            thread::sleep(Duration::from_millis(300));
+            keys.send(Key::Down).unwrap();
+            keys.send(Key::Char('\n')).unwrap();
+        });
+
+        let dropdown = Dropdown::with_terminal(screen.clone(), 6).unwrap();
+        let readkeys = Readkeys::with_source("", source);
+        let completer = Box::new(ListCompleter::from_receiver(incoming));
+        let mut prompt: Prompt = DropdownPrompt::new(String::new(), readkeys, dropdown, completer);
+        let selection = prompt.pick().unwrap().unwrap();
+        feeder.join().unwrap();
+
+        assert_eq!(selection.items, vec!["beta"]);
+        assert!(!prompt.completer.streaming());
+    }
+}
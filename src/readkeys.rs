@@ -1,14 +1,20 @@
 use nlp_tokenize::{WhitePunctTokenizer, Tokenizer};
 use regex;
-use std::cmp::{min, max};
-use std::io::{self, stdin};
-use std::sync::mpsc::Receiver;
+use std::io;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 use std::sync::mpsc::channel;
 use std::thread;
+use std::time::Duration;
 use termion::event::Key;
+use termion::get_tty;
 use termion::input::TermRead;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
+use ::bindings::{Action, Bindings, Keymap};
+use ::keys::KeySource;
 use ::ring_buffer::RingBuffer;
+use ::util::DESCRIPTION_SEPARATOR;
 
 pub struct ReadkeysState {
     value: String,
@@ -17,50 +23,200 @@ pub struct ReadkeysState {
 
 pub struct Readkeys {
     pub value: String,
+    /// Byte offset into `value`, always at the edge of a grapheme
     pub cursor: usize,
-    keys: Receiver<Result<Key, io::Error>>,
+    keys: Box<dyn KeySource>,
     tokenizer: WhitePunctTokenizer,
     state_history: Vec<ReadkeysState>,
     kill_ring: RingBuffer<String>,
     last_event: ReadEvent,
+    expect: Vec<Key>,
+    bindings: Bindings,
+    keymap: Keymap,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ReadEvent {
     Exit,
-    Submit,
+    /// One of the `expect`ed keys, which accept without editing
+    Accept(Key),
+    /// A key bound to something the prompt does, like accepting or moving
+    /// through the candidates
+    Action(Action, Key),
     CursorMove,
-    Tab,
     Other,
     Yank,
+    /// A key without a binding
     Key(Key),
 }
 
 pub trait Printable {
     fn width(&self) -> usize;
     fn without_escape_codes(&self) -> Self;
+    fn value(&self) -> Self;
+    fn description(&self) -> Option<Self> where Self: Sized;
+    /// Cuts the text to `width` columns, ending with `…` when anything was
+    /// cut and keeping every escape code so styles are still reset
+    fn truncated(&self, width: usize) -> Self;
 }
 
 
 impl Printable for String {
     fn width(&self) -> usize {
-        self.without_escape_codes().chars().count()
+        UnicodeWidthStr::width(&*self.without_escape_codes())
     }
 
     fn without_escape_codes(&self) -> Self {
         regex::Regex::new(r"\x1b\[[;\d]*[A-Za-z]").unwrap().replace_all(self, "").to_string()
     }
+
+    fn value(&self) -> Self {
+        let plain = self.without_escape_codes();
+        plain.split(DESCRIPTION_SEPARATOR).next().unwrap_or("").to_string()
+    }
+
+    fn description(&self) -> Option<Self> {
+        self.find(DESCRIPTION_SEPARATOR).map(|idx| self[idx + 1..].to_string())
+    }
+
+    fn truncated(&self, width: usize) -> Self {
+        if self.width() <= width {
+            return self.clone()
+        }
+        let mut truncated = String::with_capacity(self.len());
+        let mut used = 0;
+        let mut last = 0;
+        let mut text = |truncated: &mut String, text: &str| {
+            for grapheme in text.graphemes(true) {
+                let columns = grapheme.width();
+                if used + columns < width {
+                    truncated.push_str(grapheme);
+                    used += columns;
+                } else if used < width {
+                    truncated.push('…');
+                    used = width;
+                }
+            }
+        };
+        for code in regex::Regex::new(r"\x1b\[[;\d]*[A-Za-z]").unwrap().find_iter(self) {
+            text(&mut truncated, &self[last..code.start()]);
+            truncated.push_str(code.as_str());
+            last = code.end();
+        }
+        text(&mut truncated, &self[last..]);
+        truncated
+    }
 }
 
+/// Reads keys from the controlling terminal rather than stdin, which may
+/// be carrying candidates
 pub fn async_keys() -> Receiver<Result<Key, io::Error>> {
     let (tx, rx) = channel();
     thread::spawn(move|| {
-        for c in stdin().keys() {
+        let tty = match get_tty() {
+            Ok(tty) => tty,
+            Err(error) => { let _ = tx.send(Err(error)); return },
+        };
+        for c in tty.keys() {
             if let Err(_) =  tx.send(c) { break }
         }
     });
     rx
 }
 
+/// Reads key names like `enter`, `ctrl-o`, `alt-e` or `f2`
+pub fn parse_key(name: &str) -> Option<Key> {
+    let single = |name: &str| {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    let key = match &*name.to_lowercase() {
+        "enter" | "return"   => Key::Char('\n'),
+        "alt-enter"          => Key::Alt('\r'),
+        "tab"                => Key::Char('\t'),
+        "space"              => Key::Char(' '),
+        "ctrl-space"         => Key::Null,
+        "esc"                => Key::Esc,
+        "backspace" | "bspace" => Key::Backspace,
+        "del" | "delete"     => Key::Delete,
+        "insert"             => Key::Insert,
+        "home"               => Key::Home,
+        "end"                => Key::End,
+        "up"                 => Key::Up,
+        "down"               => Key::Down,
+        "left"               => Key::Left,
+        "right"              => Key::Right,
+        "pgup" | "page-up"   => Key::PageUp,
+        "pgdn" | "page-down" => Key::PageDown,
+        "btab" | "shift-tab" => Key::BackTab,
+        "ctrl-left"          => Key::CtrlLeft,
+        "ctrl-right"         => Key::CtrlRight,
+        "ctrl-up"            => Key::CtrlUp,
+        "ctrl-down"          => Key::CtrlDown,
+        "ctrl-home"          => Key::CtrlHome,
+        "ctrl-end"           => Key::CtrlEnd,
+        "alt-left"           => Key::AltLeft,
+        "alt-right"          => Key::AltRight,
+        "alt-up"             => Key::AltUp,
+        "alt-down"           => Key::AltDown,
+        "shift-left"         => Key::ShiftLeft,
+        "shift-right"        => Key::ShiftRight,
+        "shift-up"           => Key::ShiftUp,
+        "shift-down"         => Key::ShiftDown,
+        lower if lower.starts_with("ctrl-") => Key::Ctrl(single(&lower[5..])?),
+        lower if lower.starts_with("alt-") => Key::Alt(single(&name[4..])?),
+        lower if lower.starts_with('f') && lower.len() > 1 => Key::F(lower[1..].parse().ok()?),
+        _ => Key::Char(single(name)?),
+    };
+    Some(key)
+}
+
+/// Names a key the way `parse_key` reads it
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char('\n') => "enter".to_string(),
+        Key::Char('\t') => "tab".to_string(),
+        Key::Char(' ')  => "space".to_string(),
+        Key::Char(c)    => c.to_string(),
+        Key::Alt('\r')  => "alt-enter".to_string(),
+        Key::Ctrl(c)    => format!("ctrl-{}", c),
+        Key::Alt(c)     => format!("alt-{}", c),
+        Key::F(n)       => format!("f{}", n),
+        Key::Null       => "ctrl-space".to_string(),
+        Key::Esc        => "esc".to_string(),
+        Key::Backspace  => "backspace".to_string(),
+        Key::Delete     => "del".to_string(),
+        Key::Insert     => "insert".to_string(),
+        Key::Home       => "home".to_string(),
+        Key::End        => "end".to_string(),
+        Key::Up         => "up".to_string(),
+        Key::Down       => "down".to_string(),
+        Key::Left       => "left".to_string(),
+        Key::Right      => "right".to_string(),
+        Key::PageUp     => "pgup".to_string(),
+        Key::PageDown   => "pgdn".to_string(),
+        Key::BackTab    => "btab".to_string(),
+        Key::CtrlLeft   => "ctrl-left".to_string(),
+        Key::CtrlRight  => "ctrl-right".to_string(),
+        Key::CtrlUp     => "ctrl-up".to_string(),
+        Key::CtrlDown   => "ctrl-down".to_string(),
+        Key::CtrlHome   => "ctrl-home".to_string(),
+        Key::CtrlEnd    => "ctrl-end".to_string(),
+        Key::AltLeft    => "alt-left".to_string(),
+        Key::AltRight   => "alt-right".to_string(),
+        Key::AltUp      => "alt-up".to_string(),
+        Key::AltDown    => "alt-down".to_string(),
+        Key::ShiftLeft  => "shift-left".to_string(),
+        Key::ShiftRight => "shift-right".to_string(),
+        Key::ShiftUp    => "shift-up".to_string(),
+        Key::ShiftDown  => "shift-down".to_string(),
+        _               => String::new(),
+    }
+}
+
 pub enum Goto {
     BeginningOfLine,
     EndOfLine,
@@ -72,51 +228,154 @@ pub enum Goto {
 
 impl Readkeys {
     pub fn new<S: Into<String>>(value: S) -> Self {
+        Readkeys::with_source(value, async_keys())
+    }
+
+    /// Reads `keys` in order instead of the terminal, exiting after the
+    /// last one
+    pub fn scripted<S: Into<String>>(value: S, keys: Vec<Key>) -> Self {
+        let (tx, rx) = channel::<Key>();
+        for key in keys {
+            tx.send(key).unwrap();
+        }
+        Readkeys::with_source(value, rx)
+    }
+
+    /// Reads keys from `source`, such as a `Replay` or a channel
+    pub fn with_source<S, K>(value: S, source: K) -> Self where S: Into<String>, K: KeySource + 'static {
         let value = value.into();
         Self {
             cursor: value.len(),
-            keys: async_keys(),
+            keys: Box::new(source),
             tokenizer: WhitePunctTokenizer::new(),
             state_history: Vec::new(),
             kill_ring: RingBuffer::new(),
             last_event: ReadEvent::Other,
+            expect: vec![],
+            bindings: Bindings::default(),
+            keymap: Keymap::Insert,
             value,
         }
     }
 
+    /// Replaces the default emacs-style bindings
+    pub fn bindings(&mut self, bindings: Bindings) -> &mut Self {
+        self.bindings = bindings;
+        self.keymap = Keymap::Insert;
+        self
+    }
+
+    /// Whether keys are typed in or, in vi's normal state, are commands
+    pub fn keymap(&self) -> Keymap {
+        self.keymap
+    }
+
+    /// Keys that accept the prompt as they are, taking precedence over
+    /// their usual meaning
+    pub fn expect(&mut self, keys: Vec<Key>) -> &mut Self {
+        self.expect = keys;
+        self
+    }
+
+    /// Waits for the next key. A terminal that can't be read exits.
     pub fn recv<'a>(&'a mut self) -> &'a ReadEvent {
-        let key = self.keys.recv().unwrap().unwrap();
-        let mut event = ReadEvent::Key(key);
-        match key {
-            Key::Esc           => event = ReadEvent::Exit,
-            Key::Ctrl('c')     => event = ReadEvent::Exit,
-            Key::Ctrl('g')     => event = ReadEvent::Exit,
-            Key::Char('\n')    => event = ReadEvent::Submit,
-            Key::Char('\t')    => event = ReadEvent::Tab,
-            Key::Ctrl('d')     => if self.value.len() == 0 { event = ReadEvent::Exit },
-            Key::Backspace     => self.backspace(),
-            Key::Ctrl('h')     => self.backspace(),
-            Key::Ctrl('e')     => self.move_cursor(Goto::EndOfLine),
-            Key::Ctrl('a')     => self.move_cursor(Goto::BeginningOfLine),
-            Key::Ctrl('b')     => self.move_cursor(Goto::BackwardsCharacter),
-            Key::Left          => self.move_cursor(Goto::BackwardsCharacter),
-            Key::Alt('b')      => self.move_cursor(Goto::BackwardsWord),
-            Key::Alt('f')      => self.move_cursor(Goto::ForwardsWord),
-            Key::Ctrl('f')     => self.move_cursor(Goto::ForwardsCharacter),
-            Key::Right         => self.move_cursor(Goto::ForwardsCharacter),
-            Key::Alt('\u{7f}') => self.backspace_word(),
-            Key::Ctrl('7')     => self.pop_state(),
-            Key::Ctrl('u')     => self.kill_before_cursor(),
-            Key::Ctrl('k')     => self.kill_after_cursor(),
-            Key::Char(c)       => self.write(&*c.to_string()),
-            Key::Ctrl('y')     => { self.yank();      event = ReadEvent::Yank },
-            Key::Alt('y')      => { self.yank_next(); event = ReadEvent::Yank },
-            _                  => (),
+        match self.keys.next_key(None) {
+            Ok(key) => self.handle(key),
+            Err(_) => self.exit(),
+        }
+    }
+
+    /// Like `recv`, but gives up after `timeout` without a key
+    pub fn recv_timeout<'a>(&'a mut self, timeout: Duration) -> Option<&'a ReadEvent> {
+        match self.keys.next_key(Some(timeout)) {
+            Ok(key) => Some(self.handle(key)),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(self.exit()),
+        }
+    }
+
+    fn exit<'a>(&'a mut self) -> &'a ReadEvent {
+        self.last_event = ReadEvent::Exit;
+        &self.last_event
+    }
+
+    pub fn last_event(&self) -> &ReadEvent {
+        &self.last_event
+    }
+
+    fn handle<'a>(&'a mut self, key: Key) -> &'a ReadEvent {
+        if self.expect.contains(&key) {
+            self.last_event = ReadEvent::Accept(key);
+            return &self.last_event
+        }
+
+        let event = match self.bindings.action(self.keymap, key) {
+            Some(action) => self.perform(action, key),
+            None => match key {
+                Key::Char(c) if self.keymap == Keymap::Insert && !c.is_control() => {
+                    self.write(&*c.to_string());
+                    ReadEvent::Other
+                }
+                _ => ReadEvent::Key(key),
+            },
         };
         self.last_event = event;
         &self.last_event
     }
 
+    fn perform(&mut self, action: Action, key: Key) -> ReadEvent {
+        match action {
+            Action::Accept | Action::AcceptRaw | Action::Complete | Action::Next | Action::Prev |
+            Action::ToggleMode | Action::ToggleMark => return ReadEvent::Action(action, key),
+            Action::Cancel             => return ReadEvent::Exit,
+            Action::DeleteCharOrCancel if self.value.is_empty() => return ReadEvent::Exit,
+            Action::DeleteCharOrCancel => self.delete_char(),
+            Action::BackwardChar       => { self.move_cursor(Goto::BackwardsCharacter); return ReadEvent::CursorMove },
+            Action::ForwardChar        => { self.move_cursor(Goto::ForwardsCharacter); return ReadEvent::CursorMove },
+            Action::BackwardWord       => { self.move_cursor(Goto::BackwardsWord); return ReadEvent::CursorMove },
+            Action::ForwardWord        => { self.move_cursor(Goto::ForwardsWord); return ReadEvent::CursorMove },
+            Action::BeginningOfLine    => { self.move_cursor(Goto::BeginningOfLine); return ReadEvent::CursorMove },
+            Action::EndOfLine          => { self.move_cursor(Goto::EndOfLine); return ReadEvent::CursorMove },
+            Action::BackwardDeleteChar => self.backspace(),
+            Action::DeleteChar         => self.delete_char(),
+            Action::BackwardKillWord   => self.backspace_word(),
+            Action::KillWord           => self.kill_word(),
+            Action::BackwardKillLine   => self.kill_before_cursor(),
+            Action::KillLine           => self.kill_after_cursor(),
+            Action::Yank               => { self.yank(); return ReadEvent::Yank },
+            Action::YankPop            => { self.yank_next(); return ReadEvent::Yank },
+            Action::Undo               => self.pop_state(),
+            Action::ViNormal           => {
+                self.keymap = Keymap::Normal;
+                self.move_cursor(Goto::BackwardsCharacter);
+            }
+            Action::ViInsert           => self.keymap = Keymap::Insert,
+            Action::ViAppend           => {
+                self.keymap = Keymap::Insert;
+                self.move_cursor(Goto::ForwardsCharacter);
+            }
+            Action::ViInsertAtStart    => {
+                self.keymap = Keymap::Insert;
+                self.move_cursor(Goto::BeginningOfLine);
+            }
+            Action::ViAppendAtEnd      => {
+                self.keymap = Keymap::Insert;
+                self.move_cursor(Goto::EndOfLine);
+            }
+            Action::ViChangeToEnd      => {
+                self.keymap = Keymap::Insert;
+                self.kill_after_cursor();
+            }
+            Action::ViChangeLine       => {
+                self.keymap = Keymap::Insert;
+                self.move_cursor(Goto::BeginningOfLine);
+                self.kill_after_cursor();
+            }
+            Action::Ignore             => return ReadEvent::Key(key),
+        };
+        ReadEvent::Other
+    }
+
     fn push_state(&mut self) {
         self.state_history.push(ReadkeysState { value: self.value.clone(), cursor: self.cursor });
     }
@@ -149,10 +408,22 @@ impl Readkeys {
 
     pub fn backspace(&mut self) {
         self.push_state();
-        if self.cursor > 0 {
-            self.value.remove(self.cursor-1);
-            self.cursor -= 1;
-        }
+        let start = self.previous_grapheme();
+        self.value.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    pub fn delete_char(&mut self) {
+        self.push_state();
+        let end = self.next_grapheme();
+        self.value.drain(self.cursor..end);
+    }
+
+    pub fn kill_word(&mut self) {
+        self.push_state();
+        let end = self.next_word_end();
+        self.kill_ring.insert(self.value[self.cursor..end].to_string());
+        self.value = format!("{}{}", &self.value[..self.cursor], &self.value[end..]);
     }
 
     pub fn backspace_word(&mut self) {
@@ -181,31 +452,53 @@ impl Readkeys {
         self.cursor += value.len();
     }
 
-    fn increment_cursor(&mut self, distance: i32) {
-        self.cursor = self.bound_cursor(self.cursor as i32 + distance);
+    /// Columns the value takes up before the cursor
+    pub fn cursor_width(&self) -> usize {
+        self.value[..self.cursor].width()
     }
 
-    fn bound_cursor(&self, cursor: i32) -> usize{
-        min(self.value.len(), max(0, cursor) as usize) as usize
+    fn previous_grapheme(&self) -> usize {
+        self.value[..self.cursor].grapheme_indices(true).next_back().map(|(idx, _)| idx).unwrap_or(0)
     }
 
+    fn next_grapheme(&self) -> usize {
+        self.cursor + self.value[self.cursor..].graphemes(true).next().map(str::len).unwrap_or(0)
+    }
+
+    /// The grapheme edge at or before `offset`
+    fn grapheme_start(&self, offset: usize) -> usize {
+        self.value.grapheme_indices(true).map(|(idx, _)| idx)
+            .take_while(|&idx| idx <= offset)
+            .last()
+            .unwrap_or(0)
+    }
+
+    /// The grapheme edge at or after `offset`
+    fn grapheme_end(&self, offset: usize) -> usize {
+        self.value.grapheme_indices(true).map(|(idx, _)| idx)
+            .find(|&idx| idx >= offset)
+            .unwrap_or(self.value.len())
+    }
+
+    // The tokenizer splits words at combining marks, so its offsets are
+    // snapped outwards to keep whole graphemes on either side
     fn previous_word_start(&self) -> usize {
         let tokens = self.tokenizer.tokenize(&self.value[..self.cursor]);
-        if tokens.len() > 0 { tokens[tokens.len() - 1].0 } else { self.cursor }
+        if tokens.len() > 0 { self.grapheme_start(tokens[tokens.len() - 1].0) } else { self.cursor }
     }
 
     fn next_word_end(&self) -> usize {
         let tokens = self.tokenizer.tokenize(&self.value[self.cursor..]);
-        self.cursor + if tokens.len() > 0 { tokens[0].1 } else { 0 }
+        self.grapheme_end(self.cursor + if tokens.len() > 0 { tokens[0].1 } else { 0 })
     }
 
     pub fn move_cursor(&mut self, to: Goto) {
         match to {
             Goto::BeginningOfLine    => self.cursor = 0,
             Goto::EndOfLine          => self.cursor = self.value.len(),
-            Goto::BackwardsCharacter => self.increment_cursor(-1),
+            Goto::BackwardsCharacter => self.cursor = self.previous_grapheme(),
             Goto::BackwardsWord      => self.cursor = self.previous_word_start(),
-            Goto::ForwardsCharacter  => self.increment_cursor(1),
+            Goto::ForwardsCharacter  => self.cursor = self.next_grapheme(),
             Goto::ForwardsWord       => self.cursor = self.next_word_end(),
         }
     }
@@ -219,8 +512,21 @@ impl Readkeys {
 
 #[cfg(test)]
 mod test {
-    use termion::color;
-    use readkeys::Printable;
+    use termion::{color, style};
+    use termion::event::Key;
+    use ::bindings::Bindings;
+    use readkeys::{Printable, ReadEvent, Readkeys, key_name, parse_key};
+
+    fn typed(bindings: Bindings, keys: Vec<Key>) -> (String, usize) {
+        let mut readkeys = Readkeys::scripted("", keys);
+        readkeys.bindings(bindings);
+        while *readkeys.recv() != ReadEvent::Exit {}
+        (readkeys.value, readkeys.cursor)
+    }
+
+    fn chars(text: &str) -> Vec<Key> {
+        text.chars().map(Key::Char).collect()
+    }
 
     #[test]
     fn test_printable_string_strip_escape_characters() {
@@ -232,7 +538,88 @@ mod test {
     #[test]
     fn test_printable_string_width() {
         let actual = format!("{}123", color::Fg(color::Blue)).width();
-        assert_eq!(actual, 3)
+        assert_eq!(actual, 3);
+        assert_eq!("cafe\u{301}".to_string().width(), 4);
+        assert_eq!("漢字".to_string().width(), 4);
+    }
+
+    #[test]
+    fn test_printable_string_description() {
+        let candidate = format!("{}ls{}\t/bin", color::Fg(color::Blue), color::Fg(color::Reset));
+        assert_eq!(candidate.value(), "ls");
+        assert_eq!(candidate.description(), Some("/bin".to_string()));
+        assert_eq!("ls".to_string().description(), None);
+    }
+
+    #[test]
+    fn test_printable_string_truncated() {
+        let described = format!("ls  {}/usr/bin{}", style::Faint, style::Reset);
+        assert_eq!(described.truncated(20), described);
+        assert_eq!(described.truncated(7), format!("ls  {}/u…{}", style::Faint, style::Reset));
+        assert_eq!("漢字漢字".to_string().truncated(5), "漢字…");
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("ctrl-o"), Some(Key::Ctrl('o')));
+        assert_eq!(parse_key("Ctrl-O"), Some(Key::Ctrl('o')));
+        assert_eq!(parse_key("alt-E"), Some(Key::Alt('E')));
+        assert_eq!(parse_key("f12"), Some(Key::F(12)));
+        assert_eq!(parse_key("enter"), Some(Key::Char('\n')));
+        assert_eq!(parse_key("f"), Some(Key::Char('f')));
+        assert_eq!(parse_key("ctrl-"), None);
+        assert_eq!(parse_key("bogus"), None);
+
+        for key in vec![Key::Ctrl('x'), Key::Alt('.'), Key::F(3), Key::Char('\t'), Key::PageDown, Key::Null, Key::CtrlLeft, Key::Alt('\r')] {
+            assert_eq!(parse_key(&*key_name(key)), Some(key));
+        }
+    }
+
+    #[test]
+    fn test_emacs_editing() {
+        let mut keys = chars("foo bar baz");
+        keys.extend(vec![Key::Home, Key::CtrlRight, Key::Alt('d'), Key::Delete, Key::End, Key::Ctrl('y')]);
+        assert_eq!(typed(Bindings::default(), keys), ("foobaz bar".to_string(), 10));
+    }
+
+    #[test]
+    fn test_vi_editing() {
+        let mut keys = chars("src/main");
+        keys.extend(vec![Key::Esc, Key::Char('0'), Key::Char('x'), Key::Char('A')]);
+        keys.extend(chars(".rs"));
+        keys.extend(vec![Key::Esc, Key::Char('b'), Key::Char('i'), Key::Char('_')]);
+        assert_eq!(typed(Bindings::vi(), keys), ("rc/main._rs".to_string(), 9));
+    }
+
+    #[test]
+    fn test_grapheme_editing() {
+        let mut readkeys = Readkeys::scripted("cafe\u{301} 漢字", vec![Key::Left, Key::Backspace]);
+        while *readkeys.recv() != ReadEvent::Exit {}
+        assert_eq!(readkeys.value, "cafe\u{301} 字");
+        assert_eq!(readkeys.cursor_width(), 5);
+
+        let mut keys = chars("cafe\u{301}s");
+        keys.extend(vec![Key::Left, Key::Left, Key::Delete, Key::Left, Key::Backspace]);
+        assert_eq!(typed(Bindings::default(), keys), ("cfs".to_string(), 1));
+    }
+
+    #[test]
+    fn test_grapheme_word_editing() {
+        let edited = |keys: Vec<Key>| {
+            let mut readkeys = Readkeys::scripted("cafe\u{301} 日本語 テスト", keys);
+            while *readkeys.recv() != ReadEvent::Exit {}
+            (readkeys.value.clone(), readkeys.cursor)
+        };
+        let value = "cafe\u{301} 日本語 テスト".to_string();
+
+        assert_eq!(edited(vec![Key::Home, Key::Alt('f')]), (value.clone(), 6));
+        assert_eq!(edited(vec![Key::Home, Key::Alt('f'), Key::Alt('f')]), (value.clone(), 16));
+        assert_eq!(edited(vec![Key::Alt('b')]), (value.clone(), 17));
+        assert_eq!(edited(vec![Key::Alt('b'), Key::Alt('b')]), (value.clone(), 7));
+        assert_eq!(edited(vec![Key::Home, Key::Alt('d')]), (" 日本語 テスト".to_string(), 0));
+        assert_eq!(edited(vec![Key::Home, Key::Alt('f'), Key::Alt('d')]), ("cafe\u{301} テスト".to_string(), 6));
+        assert_eq!(edited(vec![Key::Alt('\u{7f}')]), ("cafe\u{301} 日本語 ".to_string(), 17));
+        assert_eq!(edited(vec![Key::Alt('b'), Key::Alt('\u{7f}')]), ("cafe\u{301} テスト".to_string(), 7));
     }
 
 }
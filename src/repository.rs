@@ -0,0 +1,315 @@
+use ::util::{absolute_path, canonicalize, read_file};
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a discovered repository, or the lack of one, is trusted before
+/// looking again, so a long-lived server sees `git init` and clones
+pub const DISCOVERY_TTL: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    static ref DISCOVERED: Mutex<HashMap<PathBuf, (Instant, Option<Repository>)>> = Mutex::new(HashMap::new());
+    static ref OUTERMOST: Mutex<HashMap<PathBuf, (Instant, Repository)>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    /// A work tree with a `.git` directory
+    Main,
+    /// A linked work tree created by `git worktree add`
+    Worktree,
+    /// A submodule checkout whose git directory lives in the superproject's
+    /// `modules` directory
+    Submodule,
+}
+
+#[derive(Clone, Debug)]
+pub struct Repository {
+    pub work_tree: PathBuf,
+    pub git_dir: PathBuf,
+    pub common_dir: PathBuf,
+    pub kind: Kind,
+}
+
+/// Runs git in `root`, returning stdout only if the command succeeded
+pub fn git_output<P: AsRef<Path>>(root: P, args: &[&str]) -> Option<String> {
+    Command::new("git").args(args).current_dir(root).output().ok()
+        .and_then(|output| if output.status.success() { Some(output.stdout) } else { None })
+        .and_then(|stdout| String::from_utf8(stdout).ok())
+}
+
+fn read_trimmed<P: AsRef<Path>>(path: P) -> Option<String> {
+    let contents = read_file(path).trim().to_string();
+    if contents.is_empty() { None } else { Some(contents) }
+}
+
+/// Resolves a `gitdir: <path>` file relative to the directory containing it
+fn read_gitfile<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    let contents = read_trimmed(&path)?;
+    if !contents.starts_with("gitdir:") {
+        return None
+    }
+    let git_dir = PathBuf::from(contents["gitdir:".len()..].trim());
+    let parent = path.as_ref().parent().unwrap_or(Path::new("."));
+    Some(canonicalize(parent.join(git_dir)))
+}
+
+/// The directory holding objects and refs, which differs from the git
+/// directory for linked worktrees
+fn common_dir<P: AsRef<Path>>(git_dir: P) -> PathBuf {
+    match read_trimmed(git_dir.as_ref().join("commondir")) {
+        Some(common) => canonicalize(git_dir.as_ref().join(common)),
+        None => git_dir.as_ref().to_owned(),
+    }
+}
+
+fn is_git_dir<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    path.join("HEAD").is_file() && (path.join("objects").is_dir() || path.join("commondir").is_file())
+}
+
+/// Whether a git directory was absorbed into another's `modules`, as git
+/// does for submodules, rather than separated with `--separate-git-dir`
+fn is_module_dir<P: AsRef<Path>>(git_dir: P) -> bool {
+    git_dir.as_ref().ancestors()
+        .any(|dir| dir.file_name() == Some("modules".as_ref()) && dir.parent().map(is_git_dir).unwrap_or(false))
+}
+
+impl Repository {
+    fn open<W: AsRef<Path>, G: AsRef<Path>>(work_tree: W, git_dir: G, gitfile: bool) -> Option<Repository> {
+        let git_dir = git_dir.as_ref().to_owned();
+        if !is_git_dir(&git_dir) {
+            return None
+        }
+        let common_dir = common_dir(&git_dir);
+        let kind = if common_dir != git_dir {
+            Kind::Worktree
+        } else if gitfile && is_module_dir(&git_dir) {
+            Kind::Submodule
+        } else {
+            Kind::Main
+        };
+        Some(Repository { work_tree: work_tree.as_ref().to_owned(), git_dir, common_dir, kind })
+    }
+
+    /// Honors `GIT_DIR` and `GIT_WORK_TREE` the same way git does
+    fn from_env() -> Option<Repository> {
+        Repository::from_git_dir(env::var_os("GIT_DIR")?, env::var_os("GIT_WORK_TREE"))
+    }
+
+    fn from_git_dir<G: AsRef<Path>, W: AsRef<Path>>(git_dir: G, work_tree: Option<W>) -> Option<Repository> {
+        let git_dir = canonicalize(absolute_path(git_dir));
+        let work_tree = match work_tree {
+            Some(work_tree) => canonicalize(absolute_path(work_tree)),
+            None => env::current_dir().ok()?,
+        };
+        Repository::open(work_tree, git_dir, false)
+    }
+
+    fn at<P: AsRef<Path>>(dir: P) -> Option<Repository> {
+        let dot_git = dir.as_ref().join(".git");
+        if dot_git.is_dir() {
+            Repository::open(dir, dot_git, false)
+        } else if dot_git.is_file() {
+            Repository::open(dir, read_gitfile(&dot_git)?, true)
+        } else {
+            None
+        }
+    }
+
+    /// Paths of the initialized submodules listed in `.gitmodules`
+    pub fn submodules(&self) -> Vec<PathBuf> {
+        let gitmodules = read_trimmed(self.work_tree.join(".gitmodules")).unwrap_or_default();
+        gitmodules.lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("path"))
+            .filter_map(|line| line.split_once('=').map(|x| x.1))
+            .map(|path| self.work_tree.join(path.trim()))
+            .filter(|path| path.join(".git").exists())
+            .collect()
+    }
+
+    /// The repository this one is checked out into as a submodule
+    pub fn superproject(&self) -> Option<Repository> {
+        if self.kind == Kind::Worktree {
+            return None
+        }
+        let parent = Repository::discover(self.work_tree.parent()?)?;
+        if parent.submodules().contains(&self.work_tree) { Some(parent) } else { None }
+    }
+
+    /// Follows superprojects up to the repository that isn't a submodule,
+    /// cached like discovery since it reads every `.gitmodules` on the way
+    pub fn outermost(self) -> Repository {
+        let key = self.work_tree.clone();
+        if let Some(&(checked, ref cached)) = OUTERMOST.lock().unwrap().get(&key) {
+            if checked.elapsed() < DISCOVERY_TTL {
+                return cached.clone()
+            }
+        }
+        let outermost = match self.superproject() {
+            Some(parent) => parent.outermost(),
+            None => self,
+        };
+        OUTERMOST.lock().unwrap().insert(key, (Instant::now(), outermost.clone()));
+        outermost
+    }
+
+    /// Walks up from `path` looking for the enclosing repository. Results
+    /// are cached per directory for a few seconds so repeated lookups
+    /// don't touch the disk.
+    pub fn discover<P: AsRef<Path>>(path: P) -> Option<Repository> {
+        if let Some(repository) = Repository::from_env() {
+            return Some(repository)
+        }
+
+        let start = canonicalize(absolute_path(path));
+        let mut cache = DISCOVERED.lock().unwrap();
+        let now = Instant::now();
+        let fresh = |cached: &(Instant, Option<Repository>)| {
+            now.duration_since(cached.0) < DISCOVERY_TTL &&
+                cached.1.as_ref().map(|repository| is_git_dir(&repository.git_dir)).unwrap_or(true)
+        };
+
+        let mut visited = vec![];
+        let mut found = None;
+        let mut checked = now;
+        for dir in start.ancestors() {
+            match cache.get(dir) {
+                Some(cached) if fresh(cached) => {
+                    found = cached.1.clone();
+                    checked = cached.0;
+                    break
+                }
+                _ => (),
+            }
+            visited.push(dir.to_owned());
+            if let Some(repository) = Repository::at(dir) {
+                found = Some(repository);
+                break
+            }
+        }
+
+        for dir in visited {
+            cache.insert(dir, (checked, found.clone()));
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Kind, Repository};
+    use std::fs;
+    use std::path::Path;
+    use ::util::fixture;
+
+    fn git_dir<P: AsRef<Path>>(path: P) {
+        fs::create_dir_all(path.as_ref().join("objects")).unwrap();
+        fs::write(path.as_ref().join("HEAD"), "ref: refs/heads/master\n").unwrap();
+    }
+
+    fn gitfile<P: AsRef<Path>>(work_tree: P, git_dir: &str) {
+        fs::create_dir_all(&work_tree).unwrap();
+        fs::write(work_tree.as_ref().join(".git"), format!("gitdir: {}\n", git_dir)).unwrap();
+    }
+
+    #[test]
+    fn test_discover_main() {
+        let root = fixture("repository-main");
+        git_dir(root.join(".git"));
+        fs::create_dir(root.join("src")).unwrap();
+
+        let repository = Repository::discover(root.join("src")).unwrap();
+        assert_eq!(repository.work_tree, root);
+        assert_eq!(repository.git_dir, root.join(".git"));
+        assert_eq!(repository.kind, Kind::Main);
+    }
+
+    #[test]
+    fn test_discover_worktree() {
+        let root = fixture("repository-worktree");
+        git_dir(root.join("main/.git"));
+        let linked = root.join("main/.git/worktrees/feature");
+        fs::create_dir_all(&linked).unwrap();
+        fs::write(linked.join("HEAD"), "ref: refs/heads/feature\n").unwrap();
+        fs::write(linked.join("commondir"), "../..\n").unwrap();
+        gitfile(root.join("feature"), "../main/.git/worktrees/feature");
+
+        let repository = Repository::discover(root.join("feature")).unwrap();
+        assert_eq!(repository.work_tree, root.join("feature"));
+        assert_eq!(repository.git_dir, linked);
+        assert_eq!(repository.common_dir, root.join("main/.git"));
+        assert_eq!(repository.kind, Kind::Worktree);
+    }
+
+    #[test]
+    fn test_discover_submodule() {
+        let root = fixture("repository-submodule");
+        git_dir(root.join(".git"));
+        git_dir(root.join(".git/modules/vendor"));
+        gitfile(root.join("vendor"), "../.git/modules/vendor");
+
+        let repository = Repository::discover(root.join("vendor")).unwrap();
+        assert_eq!(repository.work_tree, root.join("vendor"));
+        assert_eq!(repository.git_dir, root.join(".git/modules/vendor"));
+        assert_eq!(repository.kind, Kind::Submodule);
+    }
+
+    #[test]
+    fn test_superproject_and_outermost() {
+        let root = fixture("repository-nested");
+        git_dir(root.join(".git"));
+        git_dir(root.join(".git/modules/vendor"));
+        git_dir(root.join(".git/modules/vendor/modules/deep"));
+        gitfile(root.join("vendor"), "../.git/modules/vendor");
+        gitfile(root.join("vendor/deep"), "../../.git/modules/vendor/modules/deep");
+        fs::write(root.join(".gitmodules"), "[submodule \"vendor\"]\n\tpath = vendor\n").unwrap();
+        fs::write(root.join("vendor/.gitmodules"), "[submodule \"deep\"]\n\tpath = deep\n").unwrap();
+
+        let deep = Repository::discover(root.join("vendor/deep")).unwrap();
+        assert_eq!(deep.superproject().unwrap().work_tree, root.join("vendor"));
+        assert_eq!(deep.clone().outermost().work_tree, root);
+        assert_eq!(deep.outermost().work_tree, root);
+
+        let top = Repository::discover(&root).unwrap();
+        assert!(top.superproject().is_none());
+        assert_eq!(top.submodules(), vec![root.join("vendor")]);
+    }
+
+    #[test]
+    fn test_discover_separate_git_dir() {
+        let root = fixture("repository-separate");
+        git_dir(root.join("store.git"));
+        gitfile(root.join("checkout"), "../store.git");
+
+        let repository = Repository::discover(root.join("checkout")).unwrap();
+        assert_eq!(repository.git_dir, root.join("store.git"));
+        assert_eq!(repository.kind, Kind::Main);
+    }
+
+    #[test]
+    fn test_git_dir_from_env() {
+        let root = fixture("repository-env");
+        git_dir(root.join("store.git"));
+        fs::create_dir(root.join("checkout")).unwrap();
+
+        let repository = Repository::from_git_dir(root.join("store.git"), Some(root.join("checkout"))).unwrap();
+        assert_eq!(repository.work_tree, root.join("checkout"));
+        assert_eq!(repository.git_dir, root.join("store.git"));
+        assert!(Repository::from_git_dir(root.join("checkout"), Some(&root)).is_none());
+    }
+
+    #[test]
+    fn test_discover_notices_removal() {
+        let root = fixture("repository-removed");
+        git_dir(root.join(".git"));
+        assert!(Repository::discover(&root).is_some());
+
+        fs::remove_dir_all(root.join(".git")).unwrap();
+        assert!(Repository::discover(&root).is_none());
+    }
+}
@@ -0,0 +1,277 @@
+use ::completer::{Accounts, CommandCompleter, Completer, HistoryCompleter, HostCompleter, JumpCompleter};
+use ::completer::{MixedCompleter, OptionCompleter, ProcessCompleter, TargetCompleter, UserCompleter};
+use ::config::Config;
+use ::errors::Result;
+use ::filter::{Ranked, SpacedFilter, rank};
+use ::frecency::Database;
+use serde_json::{self, Value};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REQUEST_CANCELLED: i64 = -32800;
+
+/// A JSON-RPC request or notification read from the client
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub id: Option<Value>,
+    pub method: String,
+    pub params: Value,
+}
+
+impl Message {
+    pub fn parse(line: &str) -> ::std::result::Result<Message, String> {
+        let message: Value = serde_json::from_str(line).map_err(|error| error.to_string())?;
+        let method = message.get("method").and_then(Value::as_str).ok_or("missing method")?;
+        Ok(Message {
+            id: message.get("id").cloned(),
+            method: method.to_string(),
+            params: message.get("params").cloned().unwrap_or(Value::Null),
+        })
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).and_then(Value::as_str)
+    }
+}
+
+/// Whether a later message already in the queue makes `message` pointless,
+/// either a newer completion or an explicit `$/cancelRequest` for it
+pub fn superseded(message: &Message, pending: &VecDeque<Message>) -> bool {
+    pending.iter().any(|later| {
+        later.method == "complete" || (later.method == "$/cancelRequest" &&
+            message.id.is_some() && later.params.get("id") == message.id.as_ref())
+    })
+}
+
+fn read_messages<R: BufRead + Send + 'static>(input: R) -> Receiver<::std::result::Result<Message, String>> {
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for line in input.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if !line.trim().is_empty() && tx.send(Message::parse(&line)).is_err() {
+                break
+            }
+        }
+    });
+    rx
+}
+
+/// Completers kept for the whole session so their caches outlive requests
+pub struct Server<M: Fn() -> MixedCompleter> {
+    config: Config,
+    mixed_completer: M,
+    mixed: Option<MixedCompleter>,
+    jump: Option<JumpCompleter>,
+    hosts: Option<HostCompleter>,
+    processes: Option<ProcessCompleter>,
+    users: Option<UserCompleter>,
+    groups: Option<UserCompleter>,
+    history: Option<HistoryCompleter>,
+    command: Option<CommandCompleter>,
+    options: HashMap<String, OptionCompleter>,
+    targets: HashMap<(String, Option<String>), TargetCompleter>,
+}
+
+fn candidates<C: Completer>(completer: &mut C, query: &str) -> Value {
+    let candidates: Vec<_> = rank::<_, SpacedFilter>(completer, query).into_iter()
+        .map(|Ranked { value, description, score, positions }| json!({
+            "value": value,
+            "description": description,
+            "score": score,
+            "positions": positions,
+        }))
+        .collect();
+    json!({ "completer": completer.label(), "candidates": candidates })
+}
+
+impl<M: Fn() -> MixedCompleter> Server<M> {
+    /// `mixed_completer` builds the file completer, which is also the
+    /// fallback for jump completion
+    pub fn new(mixed_completer: M) -> Self {
+        Server {
+            config: Config::load(),
+            mixed_completer,
+            mixed: None,
+            jump: None,
+            hosts: None,
+            processes: None,
+            users: None,
+            groups: None,
+            history: None,
+            command: None,
+            options: HashMap::new(),
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Handles a `complete` request, whose params are the `query`, the
+    /// `cwd` to complete in, and either a `completer` name or the
+    /// `command` and `previous` word to pick one as the shell would
+    pub fn complete(&mut self, message: &Message) -> ::std::result::Result<Value, String> {
+        let query = message.param("query").unwrap_or("");
+        if let Some(cwd) = message.param("cwd") {
+            env::set_current_dir(cwd).map_err(|error| format!("{}: {}", cwd, error))?;
+        }
+
+        let command = message.param("command");
+        let previous = message.param("previous");
+        let mapped = command.and_then(|command| self.config.completer_for(command, previous));
+        let name = message.param("completer").or(mapped).unwrap_or("mixed");
+
+        let mixed_completer = &self.mixed_completer;
+        Ok(match name {
+            "mixed"     => candidates(self.mixed.get_or_insert_with(mixed_completer), query),
+            "jump"      => candidates(self.jump.get_or_insert_with(|| {
+                JumpCompleter::new(Database::open(), mixed_completer())
+            }), query),
+            "hosts"     => candidates(self.hosts.get_or_insert_with(HostCompleter::default), query),
+            "processes" => candidates(self.processes.get_or_insert_with(ProcessCompleter::default), query),
+            "users"     => candidates(self.users.get_or_insert_with(|| UserCompleter::new(Accounts::Users)), query),
+            "groups"    => candidates(self.groups.get_or_insert_with(|| UserCompleter::new(Accounts::Groups)), query),
+            "history"   => candidates(self.history.get_or_insert_with(HistoryCompleter::default), query),
+            "command"   => candidates(self.command.get_or_insert_with(CommandCompleter::default), query),
+            "options"   => {
+                let command = command.ok_or("the options completer needs a command")?;
+                let run_help = self.config.runs_help(command);
+                candidates(self.options.entry(command.to_string()).or_insert_with(|| {
+                    let mut completer = OptionCompleter::new(command);
+                    completer.run_help(run_help);
+                    completer
+                }), query)
+            }
+            "targets"   => {
+                let tool = Path::new(command.unwrap_or("make")).file_name()
+                    .map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+                let previous = previous.map(str::to_string);
+                candidates(self.targets.entry((tool.clone(), previous.clone()))
+                           .or_insert_with(|| TargetCompleter::new(tool, previous)), query)
+            }
+            other => return Err(format!("unknown completer: {}", other)),
+        })
+    }
+
+    /// Answers requests read from `input` until it closes. Requests are
+    /// handled in order, and one that a later message supersedes is
+    /// answered with a cancellation error instead of being computed.
+    pub fn serve<R, W>(&mut self, input: R, mut output: W) -> Result<()>
+        where R: BufRead + Send + 'static, W: Write
+    {
+        let messages = read_messages(input);
+        let mut pending = VecDeque::new();
+
+        while let Ok(message) = messages.recv() {
+            pending.push_back(message);
+            while let Some(message) = pending.pop_front() {
+                pending.extend(messages.try_iter());
+                let message = match message {
+                    Ok(message) => message,
+                    Err(error) => {
+                        respond(&mut output, &Value::Null, Err((PARSE_ERROR, error)))?;
+                        continue
+                    }
+                };
+
+                let parsed: VecDeque<_> = pending.iter().filter_map(|m| m.as_ref().ok()).cloned().collect();
+                let response = match &*message.method {
+                    "$/cancelRequest" => continue,
+                    "complete" if superseded(&message, &parsed) => Err((REQUEST_CANCELLED, "request superseded".to_string())),
+                    "complete" => self.complete(&message).map_err(|error| (INVALID_PARAMS, error)),
+                    method => Err((METHOD_NOT_FOUND, format!("unknown method: {}", method))),
+                };
+
+                // Notifications carry no id and get no response
+                if let Some(ref id) = message.id {
+                    respond(&mut output, id, response)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn respond<W: Write>(output: &mut W, id: &Value, response: ::std::result::Result<Value, (i64, String)>) -> Result<()> {
+    let response = match response {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+    };
+    writeln!(output, "{}", response)?;
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Message, Server, superseded};
+    use ::completer::MixedCompleter;
+    use ::util::{fixture, lock_cwd, path_string};
+    use serde_json::{self, Value};
+    use std::collections::VecDeque;
+    use std::env;
+    use std::fs::File;
+    use std::io::Cursor;
+
+    fn message(line: &str) -> Message {
+        Message::parse(line).unwrap()
+    }
+
+    #[test]
+    fn test_superseded() {
+        let request = message(r#"{"jsonrpc": "2.0", "id": 1, "method": "complete", "params": {"query": "sr"}}"#);
+        let newer = message(r#"{"jsonrpc": "2.0", "id": 2, "method": "complete", "params": {"query": "src"}}"#);
+        let cancel = message(r#"{"jsonrpc": "2.0", "method": "$/cancelRequest", "params": {"id": 1}}"#);
+        let other = message(r#"{"jsonrpc": "2.0", "method": "$/cancelRequest", "params": {"id": 3}}"#);
+
+        assert!(!superseded(&request, &VecDeque::new()));
+        assert!(superseded(&request, &vec![newer].into_iter().collect()));
+        assert!(superseded(&request, &vec![cancel].into_iter().collect()));
+        assert!(!superseded(&request, &vec![other].into_iter().collect()));
+    }
+
+    #[test]
+    fn test_parse_message() {
+        assert!(Message::parse("{").is_err());
+        assert!(Message::parse(r#"{"id": 1}"#).is_err());
+        assert_eq!(message(r#"{"method": "complete"}"#).id, None);
+    }
+
+    fn serve_in<M: Fn() -> MixedCompleter>(server: &mut Server<M>, cwd: &str) -> Vec<String> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "complete",
+                              "params": { "query": "", "cwd": cwd, "completer": "mixed" } });
+        let mut output = vec![];
+        server.serve(Cursor::new(format!("{}\n", request).into_bytes()), &mut output).unwrap();
+
+        let response: Value = serde_json::from_slice(&output).unwrap();
+        response["result"]["candidates"].as_array().unwrap().iter()
+            .map(|candidate| candidate["value"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_serve_lists_each_cwd() {
+        let (first, second) = (fixture("serve-first"), fixture("serve-second"));
+        File::create(first.join("alpha.txt")).unwrap();
+        File::create(second.join("beta.txt")).unwrap();
+        let _cwd = lock_cwd();
+        let cwd = env::current_dir().unwrap();
+
+        let mut server = Server::new(MixedCompleter::default);
+        let in_first = serve_in(&mut server, &path_string(&first));
+        let in_second = serve_in(&mut server, &path_string(&second));
+        env::set_current_dir(cwd).unwrap();
+
+        assert!(in_first.contains(&"alpha.txt".to_string()), "{:?}", in_first);
+        assert!(!in_first.contains(&"beta.txt".to_string()), "{:?}", in_first);
+        assert!(in_second.contains(&"beta.txt".to_string()), "{:?}", in_second);
+        assert!(!in_second.contains(&"alpha.txt".to_string()), "{:?}", in_second);
+    }
+}
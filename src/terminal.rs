@@ -0,0 +1,315 @@
+use ::errors::Result;
+use ::util;
+use nix::libc;
+use std::cell::RefCell;
+use std::cmp::{max, min};
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::rc::Rc;
+use std::str;
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::get_tty;
+use unicode_width::UnicodeWidthChar;
+
+/// Where the dropdown is drawn
+pub trait Terminal: Write {
+    /// Columns and rows
+    fn size(&self) -> Result<(u16, u16)>;
+
+    /// Column and row of the cursor, counting from 1
+    fn cursor_pos(&mut self) -> Result<(u16, u16)>;
+
+    /// Asks whatever owns the terminal to repaint what the dropdown covered
+    fn redraw(&mut self) -> Result<()>;
+}
+
+/// The controlling terminal in raw mode
+pub struct Tty {
+    out: RawTerminal<File>,
+}
+
+impl Tty {
+    /// Fails when there is no controlling terminal
+    pub fn open() -> Result<Tty> {
+        Ok(Tty { out: get_tty()?.into_raw_mode()? })
+    }
+}
+
+impl Write for Tty {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl Terminal for Tty {
+    /// Asks the tty itself, since stdout is usually captured by the shell
+    fn size(&self) -> Result<(u16, u16)> {
+        let mut size: libc::winsize = unsafe { mem::zeroed() };
+        if unsafe { libc::ioctl(self.out.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } == -1 {
+            return Err(io::Error::last_os_error().into())
+        }
+        Ok((size.ws_col, size.ws_row))
+    }
+
+    fn cursor_pos(&mut self) -> Result<(u16, u16)> {
+        Ok(util::sync_cursor_pos(&mut self.out)?)
+    }
+
+    fn redraw(&mut self) -> Result<()> {
+        util::redraw_window()
+    }
+}
+
+struct Screen {
+    width: u16,
+    height: u16,
+    /// One string per column, so combining marks stay with their base and
+    /// the column after a double-width character is left empty
+    rows: Vec<Vec<String>>,
+    x: u16,
+    y: u16,
+    escape: Option<String>,
+    /// The start of a character split across writes
+    partial: Vec<u8>,
+    redraws: usize,
+}
+
+impl Screen {
+    fn blank(&self) -> Vec<String> {
+        vec![" ".to_string(); self.width as usize]
+    }
+
+    fn line_feed(&mut self) {
+        if self.y < self.height {
+            self.y += 1;
+        } else {
+            let blank = self.blank();
+            self.rows.remove(0);
+            self.rows.push(blank);
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        let width = c.width().unwrap_or(0) as u16;
+        if width == 0 {
+            let row = &mut self.rows[self.y as usize - 1];
+            let end = min(self.x, self.width + 1) as usize - 1;
+            if let Some(cell) = row[..end].iter_mut().rev().find(|cell| !cell.is_empty()) {
+                cell.push(c);
+            }
+            return
+        }
+        // Like a real terminal, only wrap once there is more to write
+        if self.x + width - 1 > self.width {
+            self.x = 1;
+            self.line_feed();
+        }
+        let row = &mut self.rows[self.y as usize - 1];
+        row[self.x as usize - 1] = c.to_string();
+        if width == 2 && (self.x as usize) < row.len() {
+            row[self.x as usize] = String::new();
+        }
+        self.x += width;
+    }
+
+    fn clear_line(&mut self, from: u16, to: u16) {
+        let row = &mut self.rows[self.y as usize - 1];
+        for cell in &mut row[from as usize - 1..to as usize] {
+            *cell = " ".to_string();
+        }
+    }
+
+    fn control_sequence(&mut self, params: &str, command: char) {
+        let numbers: Vec<u16> = params.trim_start_matches('?').split(';')
+            .map(|number| number.parse().unwrap_or(0))
+            .collect();
+        let n = |i: usize| max(1, numbers.get(i).cloned().unwrap_or(0));
+        let mode = numbers[0];
+
+        match command {
+            'A'       => self.y = max(1, self.y.saturating_sub(n(0))),
+            'B'       => self.y = min(self.height, self.y + n(0)),
+            'C'       => self.x = min(self.width, self.x + n(0)),
+            'D'       => self.x = max(1, min(self.width, self.x).saturating_sub(n(0))),
+            'H' | 'f' => {
+                self.y = min(self.height, n(0));
+                self.x = min(self.width, n(1));
+            }
+            'K' => {
+                let (x, width) = (min(self.x, self.width), self.width);
+                match mode {
+                    1 => self.clear_line(1, x),
+                    2 => self.clear_line(1, width),
+                    _ => self.clear_line(x, width),
+                }
+            }
+            'J' if mode == 2 => {
+                let blank = self.blank();
+                for row in &mut self.rows {
+                    *row = blank.clone();
+                }
+            }
+            // Styles, cursor reports and the rest don't change the grid
+            _ => (),
+        }
+    }
+
+    fn feed(&mut self, c: char) {
+        match self.escape.take() {
+            Some(mut sequence) => {
+                sequence.push(c);
+                if sequence.len() == 2 {
+                    if c == '[' { self.escape = Some(sequence) }
+                } else if c.is_ascii_digit() || c == ';' || c == '?' {
+                    self.escape = Some(sequence);
+                } else {
+                    self.control_sequence(&sequence[2..sequence.len() - 1], c);
+                }
+            }
+            None => match c {
+                '\x1b' => self.escape = Some(c.to_string()),
+                '\r'   => self.x = 1,
+                '\n'   => self.line_feed(),
+                '\x08' => self.x = max(1, self.x - 1),
+                c if c.is_control() => (),
+                c      => self.put(c),
+            },
+        }
+    }
+}
+
+/// An in-memory terminal that interprets what is written to it, so tests
+/// can look at the screen the dropdown leaves behind. Clones share the
+/// same screen.
+#[derive(Clone)]
+pub struct VirtualScreen {
+    screen: Rc<RefCell<Screen>>,
+}
+
+impl VirtualScreen {
+    pub fn new(width: u16, height: u16) -> Self {
+        let screen = Screen {
+            width,
+            height,
+            rows: vec![vec![" ".to_string(); width as usize]; height as usize],
+            x: 1,
+            y: 1,
+            escape: None,
+            partial: vec![],
+            redraws: 0,
+        };
+        VirtualScreen { screen: Rc::new(RefCell::new(screen)) }
+    }
+
+    /// Moves the cursor, as a shell prompt would have left it
+    pub fn with_cursor(self, x: u16, y: u16) -> Self {
+        {
+            let mut screen = self.screen.borrow_mut();
+            screen.x = x;
+            screen.y = y;
+        }
+        self
+    }
+
+    /// The rows of the screen without trailing blanks
+    pub fn lines(&self) -> Vec<String> {
+        self.screen.borrow().rows.iter()
+            .map(|row| row.concat().trim_end().to_string())
+            .collect()
+    }
+
+    /// The rows joined by newlines, with trailing blank rows dropped
+    pub fn snapshot(&self) -> String {
+        let lines = self.lines();
+        let used = lines.iter().rposition(|line| !line.is_empty()).map(|idx| idx + 1).unwrap_or(0);
+        lines[..used].join("\n")
+    }
+
+    pub fn cursor(&self) -> (u16, u16) {
+        let screen = self.screen.borrow();
+        (screen.x, screen.y)
+    }
+
+    /// How many times a repaint was asked for
+    pub fn redraws(&self) -> usize {
+        self.screen.borrow().redraws
+    }
+}
+
+impl Write for VirtualScreen {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut screen = self.screen.borrow_mut();
+        let mut bytes = mem::take(&mut screen.partial);
+        bytes.extend_from_slice(buf);
+
+        let mut rest = &bytes[..];
+        loop {
+            match str::from_utf8(rest) {
+                Ok(text) => {
+                    text.chars().for_each(|c| screen.feed(c));
+                    break
+                }
+                Err(error) => {
+                    let (valid, invalid) = rest.split_at(error.valid_up_to());
+                    str::from_utf8(valid).unwrap_or("").chars().for_each(|c| screen.feed(c));
+                    match error.error_len() {
+                        Some(len) => {
+                            screen.feed(char::REPLACEMENT_CHARACTER);
+                            rest = &invalid[len..];
+                        }
+                        None => {
+                            screen.partial = invalid.to_vec();
+                            break
+                        }
+                    }
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Terminal for VirtualScreen {
+    fn size(&self) -> Result<(u16, u16)> {
+        let screen = self.screen.borrow();
+        Ok((screen.width, screen.height))
+    }
+
+    fn cursor_pos(&mut self) -> Result<(u16, u16)> {
+        let screen = self.screen.borrow();
+        Ok((min(screen.x, screen.width), screen.y))
+    }
+
+    fn redraw(&mut self) -> Result<()> {
+        self.screen.borrow_mut().redraws += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VirtualScreen;
+    use std::io::Write;
+
+    #[test]
+    fn test_characters_split_across_writes() {
+        let mut screen = VirtualScreen::new(10, 2);
+        let text = "é漢".as_bytes();
+        for byte in text {
+            screen.write_all(&[*byte]).unwrap();
+        }
+        screen.write_all(b"\xffx").unwrap();
+        assert_eq!(screen.lines()[0], "é漢\u{fffd}x");
+        assert_eq!(screen.cursor(), (6, 1));
+    }
+}
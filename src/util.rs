@@ -1,17 +1,19 @@
+use ::accounts;
 use ::errors::Result;
+use ::repository::Repository;
 use nix::sys::signal;
+use regex::{Captures, Regex};
 use nix::unistd;
 use std::env::home_dir;
 use std::env;
 use std::fmt::Display;
-use std::io::{self, Write, Stdout, Read, stdin};
+use std::io::{self, Write, Read};
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::time::{SystemTime, Duration};
 use termion::color::{self, Green, Fg};
 use termion::raw::CONTROL_SEQUENCE_TIMEOUT;
 use termion::style::{self, Underline, Bold};
-use termion::terminal_size;
+use termion::get_tty;
 
 pub fn log<D>(value: D) where D: Display {
     use std::io::prelude::*;
@@ -23,8 +25,9 @@ pub fn log<D>(value: D) where D: Display {
 }
 
 /// Vendor this function with a small modification to avoid panic
-pub fn sync_cursor_pos(stdout: &mut Stdout) -> io::Result<(u16, u16)> {
-    let mut stdin = stdin();
+pub fn sync_cursor_pos<W: Write>(stdout: &mut W) -> io::Result<(u16, u16)> {
+    // The terminal answers on the tty, stdin may be a pipe
+    let mut stdin = get_tty()?;
 
     // Where is the cursor?
     // Use `ESC [ 6 n`.
@@ -47,13 +50,14 @@ pub fn sync_cursor_pos(stdout: &mut Stdout) -> io::Result<(u16, u16)> {
     // The answer will look like `ESC [ Cy ; Cx R`.
 
     read_chars.pop(); // remove trailing R.
-    let read_str = String::from_utf8(read_chars).unwrap();
-    let beg = read_str.rfind('[').unwrap();
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "terminal did not report the cursor position");
+    let read_str = String::from_utf8(read_chars).map_err(|_| invalid())?;
+    let beg = read_str.rfind('[').ok_or_else(invalid)?;
     let coords: String = read_str.chars().skip(beg + 1).collect();
-    let mut nums = coords.split(';');
+    let mut nums = coords.split(';').map(|num| num.parse::<u16>().map_err(|_| invalid()));
 
-    let cy = nums.next().unwrap().parse().unwrap();
-    let cx = nums.next().unwrap().parse().unwrap();
+    let cy = nums.next().ok_or_else(invalid)??;
+    let cx = nums.next().ok_or_else(invalid)??;
 
     Ok((cx, cy))
 }
@@ -64,39 +68,110 @@ pub fn redraw_window() -> Result<()> {
     Ok(signal::kill(unistd::getppid(), signal::Signal::SIGWINCH)?)
 }
 
-
-pub fn window_height() -> Result<u16> {
-    Ok(terminal_size()?.1)
+/// Expands `$VAR/` and `${VAR}/` path components from the environment,
+/// leaving unset variables as they are
+pub fn expand_variables(value: &str) -> String {
+    lazy_static! {
+        static ref VARIABLE: Regex = Regex::new(r"\$(?:\{([A-Za-z_][A-Za-z0-9_]*)\}|([A-Za-z_][A-Za-z0-9_]*))/").unwrap();
+    }
+    VARIABLE.replace_all(value, |captures: &Captures| {
+        let name = captures.get(1).or(captures.get(2)).map(|name| name.as_str()).unwrap_or("");
+        match env::var(name) {
+            Ok(expanded) => format!("{}/", expanded.trim_end_matches('/')),
+            Err(_) => captures[0].to_string(),
+        }
+    }).to_string()
 }
 
+/// Expands a leading `~/` or `~user/` and any `$VAR/` components
 pub fn expand_user<P: AsRef<Path>>(path: P) -> PathBuf {
-    if let Ok(relative_path) = path.as_ref().strip_prefix("~/") {
+    let lossy = path_string(&path);
+    let path = if lossy.contains('$') { PathBuf::from(expand_variables(&*lossy)) } else { path.as_ref().to_owned() };
+    if let Ok(relative_path) = path.strip_prefix("~/") {
         if let Some(home) = home_dir() {
             return home.join(relative_path)
         }
     }
-    path.as_ref().to_owned()
+    if let Some(expanded) = expand_other_user(&path, accounts::users()) {
+        return expanded
+    }
+    path
+}
+
+fn expand_other_user(path: &Path, users: &[accounts::User]) -> Option<PathBuf> {
+    let mut components = path.components();
+    let first = components.next()?.as_os_str().to_str()?;
+    if !first.starts_with('~') || first.len() == 1 {
+        return None
+    }
+    let user = users.iter().find(|user| user.name == first[1..])?;
+    Some(Path::new(&user.home).join(components.as_path()))
+}
+
+/// Reads a file as text, giving an empty string when it can't be read.
+/// Bytes that aren't UTF-8 are replaced rather than failing the read.
+pub fn read_file<P: AsRef<Path>>(path: P) -> String {
+    let mut contents = vec![];
+    let _ = ::std::fs::File::open(path).and_then(|mut file| file.read_to_end(&mut contents));
+    String::from_utf8_lossy(&contents).into_owned()
+}
+
+/// Separates the value a candidate inserts from its description column
+pub const DESCRIPTION_SEPARATOR: char = '\t';
+
+/// Formats a candidate that is displayed with a dimmed description column
+pub fn describe<V: Display, D: Display>(value: V, description: D) -> String {
+    format!("{}{}{}", value, DESCRIPTION_SEPARATOR, description)
+}
+
+/// Directory for caches that persist between invocations
+pub fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".cache")))
+        .map(|cache| cache.join("complesh"))
+}
+
+/// Directory holding the user's configuration
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".config")))
+        .map(|config| config.join("complesh"))
+}
+
+/// Directory for state that persists between invocations
+pub fn data_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".local").join("share")))
+        .map(|data| data.join("complesh"))
 }
 
 pub fn emphasize<D: Display>(value: D) -> String {
     format!("{}{}{}{}{}{}", Fg(Green), Underline, Bold, value, Fg(color::Reset), style::Reset)
 }
 
-pub fn within_dir<F: FnOnce() -> T, P: AsRef<Path>, T>(path: P, f: F) -> Result<T> {
-    let cwd = env::current_dir()?;
-    env::set_current_dir(path)?;
-    let result = f();
-    env::set_current_dir(cwd)?;
-    Ok(result)
+pub fn git_root<P: AsRef<Path>>(path: P) -> Result<String> {
+    if !path.as_ref().is_dir() {
+        return Err(format!("{}: not a directory", path_string(path)).into())
+    }
+    Ok(Repository::discover(path).map(|repo| path_string(repo.work_tree)).unwrap_or(String::new()))
 }
 
-pub fn git_root<P: AsRef<Path>>(path: P) -> Result<String> {
-    within_dir(path.as_ref(), || {
-        Command::new("git").arg("rev-parse").arg("--show-toplevel").output()
-            .map(|output| String::from_utf8(output.stdout))
-            .and_then(|path| Ok(path.unwrap().trim().to_string()))
-            .unwrap_or(String::new())
-    })
+/// Resolves a command to the file `$PATH` lookup would run
+pub fn which(command: &str) -> Option<PathBuf> {
+    if command.contains('/') {
+        let path = absolute_path(command);
+        return if path.is_file() { Some(path) } else { None }
+    }
+    env::var_os("PATH")
+        .and_then(|path| env::split_paths(&path).map(|dir| dir.join(command)).find(|path| path.is_file()))
+}
+
+/// Finds the nearest of the named files in `start` or one of its parents
+pub fn find_upwards<P: AsRef<Path>>(start: P, names: &[&str]) -> Option<PathBuf> {
+    let start = canonicalize(absolute_path(start));
+    start.ancestors()
+        .filter_map(|dir| names.iter().map(|name| dir.join(name)).find(|path| path.is_file()))
+        .next()
 }
 
 pub fn absolute_path<P: AsRef<Path>>(path: P) -> PathBuf {
@@ -127,6 +202,27 @@ pub fn path_string<P: AsRef<Path>>(path: P) -> String {
     path.as_ref().to_string_lossy().to_string()
 }
 
+/// A fresh, empty directory for a test to build files in
+#[cfg(test)]
+pub fn fixture(name: &str) -> PathBuf {
+    use std::{fs, process};
+    let dir = env::temp_dir().join(format!("complesh-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    canonicalize(dir)
+}
+
+/// Held by tests that change the working directory or resolve relative
+/// paths against it, since every test thread shares it
+#[cfg(test)]
+pub fn lock_cwd() -> ::std::sync::MutexGuard<'static, ()> {
+    use std::sync::Mutex;
+    lazy_static! {
+        static ref CWD: Mutex<()> = Mutex::new(());
+    }
+    CWD.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
 pub fn search_root<P: AsRef<Path>>(path: P) -> PathBuf {
     let expanded = canonicalize(expand_user(path));
     if expanded.is_dir() {
@@ -142,5 +238,26 @@ pub fn search_root<P: AsRef<Path>>(path: P) -> PathBuf {
 
 #[test]
 fn test_git_root() {
+    let _cwd = lock_cwd();
     assert!(git_root(".").is_ok());
 }
+
+#[test]
+fn test_expand_variables() {
+    env::set_var("COMPLESH_TEST_DIR", "/tmp/complesh/");
+    assert_eq!(expand_variables("$COMPLESH_TEST_DIR/src"), "/tmp/complesh/src");
+    assert_eq!(expand_variables("${COMPLESH_TEST_DIR}/src"), "/tmp/complesh/src");
+    assert_eq!(expand_variables("$COMPLESH_TEST_DIR"), "$COMPLESH_TEST_DIR");
+    assert_eq!(expand_variables("$COMPLESH_TEST_UNSET/src"), "$COMPLESH_TEST_UNSET/src");
+}
+
+#[test]
+fn test_expand_other_user() {
+    let passwd = fixture("passwd").join("passwd");
+    ::std::fs::write(&passwd, "# comment\nalice:x:1000:1000:Alice:/home/alice:/bin/sh\n").unwrap();
+    let users = accounts::parse_passwd(&read_file(&passwd));
+    assert_eq!(expand_other_user(Path::new("~alice/bin"), &users), Some(PathBuf::from("/home/alice/bin")));
+    assert_eq!(expand_other_user(Path::new("~alice"), &users), Some(PathBuf::from("/home/alice")));
+    assert_eq!(expand_other_user(Path::new("~bob/bin"), &users), None);
+    assert_eq!(expand_user("~complesh-no-such-user/bin"), PathBuf::from("~complesh-no-such-user/bin"));
+}